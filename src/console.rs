@@ -0,0 +1,106 @@
+//! Console setup.
+//!
+//! On Windows, ANSI escape sequences (used to clear the screen in watch mode) are only interpreted
+//! once virtual terminal processing is enabled on the console.  Legacy consoles not supporting it
+//! usually don't render box-drawing characters properly either, so the caller should fall back to
+//! ASCII output in this case.
+
+/// Prepare the console, and return whether it supports ANSI escape sequences and Unicode output
+#[cfg(windows)]
+pub fn init() -> bool {
+    use std::os::raw::c_void;
+
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetStdHandle(std_handle: u32) -> *mut c_void;
+        fn GetConsoleMode(console_handle: *mut c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console_handle: *mut c_void, mode: u32) -> i32;
+    }
+
+    unsafe {
+        let handle = GetStdHandle(STD_OUTPUT_HANDLE);
+        let mut mode = 0;
+        if GetConsoleMode(handle, &mut mode) == 0 {
+            // The output is not a console (ex: it is redirected to a file), keep it untouched
+            return true;
+        }
+        mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+            || SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
+    }
+}
+
+/// Prepare the console, and return whether it supports ANSI escape sequences and Unicode output
+#[cfg(not(windows))]
+pub fn init() -> bool {
+    true
+}
+
+/// Width of the terminal in columns, if the output is a terminal
+#[cfg(unix)]
+pub fn width() -> Option<usize> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+        && size.ws_col > 0
+    {
+        Some(size.ws_col as usize)
+    } else {
+        None
+    }
+}
+
+/// Width of the terminal in columns, if the output is a terminal
+#[cfg(windows)]
+pub fn width() -> Option<usize> {
+    use std::os::raw::c_void;
+
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+
+    #[repr(C)]
+    struct Coord {
+        x: i16,
+        y: i16,
+    }
+
+    #[repr(C)]
+    struct SmallRect {
+        left: i16,
+        top: i16,
+        right: i16,
+        bottom: i16,
+    }
+
+    #[repr(C)]
+    struct ConsoleScreenBufferInfo {
+        size: Coord,
+        cursor_position: Coord,
+        attributes: u16,
+        window: SmallRect,
+        maximum_window_size: Coord,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetStdHandle(std_handle: u32) -> *mut c_void;
+        fn GetConsoleScreenBufferInfo(
+            console_handle: *mut c_void,
+            info: *mut ConsoleScreenBufferInfo,
+        ) -> i32;
+    }
+
+    unsafe {
+        let mut info: ConsoleScreenBufferInfo = std::mem::zeroed();
+        if GetConsoleScreenBufferInfo(GetStdHandle(STD_OUTPUT_HANDLE), &mut info) == 0 {
+            return None;
+        }
+        Some((info.window.right - info.window.left + 1) as usize)
+    }
+}
+
+/// Width of the terminal in columns, if the output is a terminal
+#[cfg(not(any(unix, windows)))]
+pub fn width() -> Option<usize> {
+    None
+}
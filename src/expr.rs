@@ -0,0 +1,341 @@
+//! Small arithmetic and boolean expression language over branch fields.
+//!
+//! Expressions evaluate to numbers, booleans being represented as 1 (true) and 0 (false), like
+//! `ahead > 5 && behind == 0` or `ahead * 2 + behind`.  Supported operators, by increasing
+//! precedence: `||`, `&&`, comparisons (`==`, `!=`, `<`, `<=`, `>`, `>=`), `+` and `-`, `*` and
+//! `/`, unary `!` and `-`.
+
+use std::{fmt, str::FromStr};
+
+/// Branch value an expression can refer to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Ahead,
+    Behind,
+    AgeDays,
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "ahead" => Ok(Field::Ahead),
+            "behind" => Ok(Field::Behind),
+            "age_days" => Ok(Field::AgeDays),
+            _ => Err(format!(
+                "unknown field '{}', expected 'ahead', 'behind' or 'age_days'",
+                name
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+enum Node {
+    Number(f64),
+    Field(Field),
+    Not(Box<Node>),
+    Negate(Box<Node>),
+    Binary(Operator, Box<Node>, Box<Node>),
+}
+
+/// Parsed expression, keeping its source to show it back
+pub struct Expr {
+    source: String,
+    root: Node,
+}
+
+impl fmt::Debug for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.source)
+    }
+}
+
+fn boolean(value: bool) -> f64 {
+    if value {
+        1.
+    } else {
+        0.
+    }
+}
+
+impl Expr {
+    /// Source the expression was parsed from
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Evaluate the expression, getting field values from `field`
+    pub fn evaluate(&self, field: &dyn Fn(Field) -> f64) -> f64 {
+        self.root.evaluate(field)
+    }
+}
+
+impl Node {
+    fn evaluate(&self, field: &dyn Fn(Field) -> f64) -> f64 {
+        match self {
+            Node::Number(value) => *value,
+            Node::Field(name) => field(*name),
+            Node::Not(expr) => boolean(expr.evaluate(field) == 0.),
+            Node::Negate(expr) => -expr.evaluate(field),
+            Node::Binary(operator, left, right) => {
+                let left = left.evaluate(field);
+                // Short-circuit boolean operators
+                match operator {
+                    Operator::Or if left != 0. => return 1.,
+                    Operator::And if left == 0. => return 0.,
+                    _ => {}
+                }
+                let right = right.evaluate(field);
+                match operator {
+                    Operator::Or | Operator::And => boolean(right != 0.),
+                    Operator::Equal => boolean(left == right),
+                    Operator::NotEqual => boolean(left != right),
+                    Operator::Less => boolean(left < right),
+                    Operator::LessOrEqual => boolean(left <= right),
+                    Operator::Greater => boolean(left > right),
+                    Operator::GreaterOrEqual => boolean(left >= right),
+                    Operator::Add => left + right,
+                    Operator::Subtract => left - right,
+                    Operator::Multiply => left * right,
+                    Operator::Divide => left / right,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Identifier(String),
+    Operator(Operator),
+    Not,
+    OpenParen,
+    CloseParen,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+
+    while index < chars.len() {
+        let c = chars[index];
+        let next = chars.get(index + 1).copied();
+
+        if c.is_whitespace() {
+            index += 1;
+            continue;
+        }
+
+        if c.is_ascii_digit() || c == '.' {
+            let start = index;
+            while index < chars.len() && (chars[index].is_ascii_digit() || chars[index] == '.') {
+                index += 1;
+            }
+            let number: String = chars[start..index].iter().collect();
+            tokens.push(Token::Number(
+                number
+                    .parse()
+                    .map_err(|_| format!("invalid number '{}'", number))?,
+            ));
+            continue;
+        }
+
+        if c.is_alphabetic() || c == '_' {
+            let start = index;
+            while index < chars.len() && (chars[index].is_alphanumeric() || chars[index] == '_') {
+                index += 1;
+            }
+            tokens.push(Token::Identifier(chars[start..index].iter().collect()));
+            continue;
+        }
+
+        let (token, length) = match (c, next) {
+            ('|', Some('|')) => (Token::Operator(Operator::Or), 2),
+            ('&', Some('&')) => (Token::Operator(Operator::And), 2),
+            ('=', Some('=')) => (Token::Operator(Operator::Equal), 2),
+            ('!', Some('=')) => (Token::Operator(Operator::NotEqual), 2),
+            ('<', Some('=')) => (Token::Operator(Operator::LessOrEqual), 2),
+            ('>', Some('=')) => (Token::Operator(Operator::GreaterOrEqual), 2),
+            ('<', _) => (Token::Operator(Operator::Less), 1),
+            ('>', _) => (Token::Operator(Operator::Greater), 1),
+            ('+', _) => (Token::Operator(Operator::Add), 1),
+            ('-', _) => (Token::Operator(Operator::Subtract), 1),
+            ('*', _) => (Token::Operator(Operator::Multiply), 1),
+            ('/', _) => (Token::Operator(Operator::Divide), 1),
+            ('!', _) => (Token::Not, 1),
+            ('(', _) => (Token::OpenParen, 1),
+            (')', _) => (Token::CloseParen, 1),
+            _ => return Err(format!("unexpected character '{}'", c)),
+        };
+        tokens.push(token);
+        index += length;
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser, one method per precedence level
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek_operator(&self, operators: &[Operator]) -> Option<Operator> {
+        match self.tokens.get(self.position) {
+            Some(Token::Operator(operator)) if operators.contains(operator) => Some(*operator),
+            _ => None,
+        }
+    }
+
+    fn binary(
+        &mut self,
+        operators: &[Operator],
+        operand: fn(&mut Self) -> Result<Node, String>,
+    ) -> Result<Node, String> {
+        let mut expr = operand(self)?;
+        while let Some(operator) = self.peek_operator(operators) {
+            self.position += 1;
+            expr = Node::Binary(operator, Box::new(expr), Box::new(operand(self)?));
+        }
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<Node, String> {
+        self.binary(&[Operator::Or], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Node, String> {
+        self.binary(&[Operator::And], Self::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Node, String> {
+        self.binary(
+            &[
+                Operator::Equal,
+                Operator::NotEqual,
+                Operator::Less,
+                Operator::LessOrEqual,
+                Operator::Greater,
+                Operator::GreaterOrEqual,
+            ],
+            Self::sum,
+        )
+    }
+
+    fn sum(&mut self) -> Result<Node, String> {
+        self.binary(&[Operator::Add, Operator::Subtract], Self::product)
+    }
+
+    fn product(&mut self) -> Result<Node, String> {
+        self.binary(&[Operator::Multiply, Operator::Divide], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        match self.tokens.get(self.position) {
+            Some(Token::Not) => {
+                self.position += 1;
+                Ok(Node::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::Operator(Operator::Subtract)) => {
+                self.position += 1;
+                Ok(Node::Negate(Box::new(self.unary()?)))
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Node, String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        match token {
+            Some(Token::Number(value)) => Ok(Node::Number(value)),
+            Some(Token::Identifier(name)) => Ok(Node::Field(name.parse()?)),
+            Some(Token::OpenParen) => {
+                let expr = self.or()?;
+                match self.tokens.get(self.position) {
+                    Some(Token::CloseParen) => {
+                        self.position += 1;
+                        Ok(expr)
+                    }
+                    _ => Err("missing closing parenthesis".into()),
+                }
+            }
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err("unexpected end of expression".into()),
+        }
+    }
+}
+
+impl FromStr for Expr {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+        };
+        let root = parser.or()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(Expr {
+                source: source.into(),
+                root,
+            }),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(source: &str) -> f64 {
+        source
+            .parse::<Expr>()
+            .unwrap()
+            .evaluate(&|field| match field {
+                Field::Ahead => 6.,
+                Field::Behind => 0.,
+                Field::AgeDays => 10.,
+            })
+    }
+
+    #[test]
+    fn evaluates_with_precedence() {
+        assert_eq!(evaluate("ahead + behind * 2 - 1"), 5.);
+        assert_eq!(evaluate("(ahead + 2) / 2"), 4.);
+        assert_eq!(evaluate("-ahead"), -6.);
+        assert_eq!(evaluate("ahead > 5 && behind == 0"), 1.);
+        assert_eq!(evaluate("ahead > 5 && !(age_days <= 10)"), 0.);
+        assert_eq!(evaluate("behind != 0 || age_days >= 10"), 1.);
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        assert!("ahead >".parse::<Expr>().is_err());
+        assert!("(ahead".parse::<Expr>().is_err());
+        assert!("ahead behind".parse::<Expr>().is_err());
+        assert!("unknown > 1".parse::<Expr>().is_err());
+        assert!("ahead = 1".parse::<Expr>().is_err());
+    }
+}
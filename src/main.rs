@@ -1,7 +1,30 @@
-use git2::{Branch, BranchType, Oid, Repository};
-use prettytable::{format::TableFormat, Cell, Row, Table};
-use std::{fmt::Write, iter::repeat, path::PathBuf};
-use structopt::{clap::AppSettings, StructOpt};
+use expr::{Expr, Field};
+use git2::{Branch, BranchType, ErrorCode, Oid, Repository, StatusOptions};
+use prettytable::{
+    format::{consts, TableFormat},
+    Attr, Cell, Row, Table,
+};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    convert::TryFrom,
+    fmt::Write,
+    fs,
+    io::{self, BufRead},
+    iter::repeat,
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use structopt::{
+    clap::{AppSettings, Shell},
+    StructOpt,
+};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+mod console;
+mod expr;
+mod output;
 
 /// Visualize branches 'ahead' and 'behind' commits compared to a base revision or their upstream.
 #[derive(StructOpt, Debug)]
@@ -19,10 +42,61 @@ EXAMPLES:
     raw(global_settings = "&[AppSettings::DeriveDisplayOrder, AppSettings::ColoredHelp]")
 )]
 struct Opt {
-    /// Revision to use as a base
+    /// Revision to use as a base, like a branch, a remote branch ('origin/develop'), a tag, a
+    /// commit or a reflog entry ('main@{1}' or 'main@{1.day.ago}')
     #[structopt(name = "base_revision", default_value = "HEAD")]
     base_revision: String,
 
+    /// When <base_revision> is not given, use the upstream of the current branch, or else the first
+    /// existing branch among 'main', 'master' and 'develop', instead of HEAD
+    #[structopt(long = "base-branch-autodetect")]
+    autodetect_base: bool,
+
+    /// Read the base revision from the first line of the standard input instead of <base_revision>,
+    /// like 'echo origin/release | git-branches-overview --stdin-base'
+    #[structopt(
+        long = "stdin-base",
+        conflicts_with = "base_revision",
+        conflicts_with = "autodetect_base",
+        conflicts_with = "branches_from_stdin"
+    )]
+    stdin_base: bool,
+
+    /// Use this tag as a base instead of <base_revision>
+    #[structopt(long = "since-tag", name = "tag")]
+    since_tag: Option<String>,
+
+    /// Use the commit <count> first-parents back from HEAD as a base instead of <base_revision>
+    #[structopt(
+        long = "relative-base",
+        name = "relative_base",
+        value_name = "count",
+        conflicts_with = "tag"
+    )]
+    relative_base: Option<usize>,
+
+    /// Use the merge base of these two revisions as a base instead of <base_revision>, like the
+    /// point where 'main' and 'release' diverged
+    #[structopt(
+        long = "base-merge-base",
+        name = "merge_base_revisions",
+        value_name = "revision",
+        raw(number_of_values = "2"),
+        conflicts_with = "tag",
+        conflicts_with = "relative_base"
+    )]
+    merge_base_revisions: Vec<String>,
+
+    /// Use <revision> as a base for branches matching <pattern>, as '<pattern>=<revision>';  the
+    /// first matching rule wins;  can be specified multiple times
+    #[structopt(
+        long = "base-for",
+        name = "rule",
+        number_of_values = 1,
+        parse(try_from_str = "parse_base_rule")
+    )]
+    base_rules: Vec<BaseRule>,
+
     /// Show local branches (default)
     #[structopt(short = "l")]
     local_branches: bool,
@@ -39,250 +113,3914 @@ struct Opt {
     #[structopt(short = "u", long = "--upstreams")]
     compare_with_upstream_branches: bool,
 
+    /// Only list branches with an upstream on this remote;  implies '-u'
+    #[structopt(long = "upstream-remote", name = "upstream_remote_name")]
+    upstream_remote: Option<String>,
+
+    /// Only list local branches with commits not pushed to their upstream;  implies '-u'
+    #[structopt(long = "needs-push")]
+    needs_push: bool,
+
+    /// Only list local branches missing commits from their upstream, and report local branches
+    /// without upstream;  implies '-u'
+    #[structopt(long = "needs-pull")]
+    needs_pull: bool,
+
+    /// Compare remote branches with the local branch of the same name instead of the default
+    /// branch;  implies '-r'
+    #[structopt(
+        long = "local-name",
+        conflicts_with = "compare_with_upstream_branches",
+        conflicts_with = "upstream_remote_name",
+        conflicts_with = "needs_push",
+        conflicts_with = "needs_pull"
+    )]
+    compare_with_local_branches: bool,
+
+    /// Compare each branch with the point where it diverged from its base, showing only how many
+    /// commits it has since then
+    #[structopt(long = "since-divergence")]
+    since_divergence: bool,
+
     /// Only list branches from those remotes;  can be specified multiple times;  implies '-r'
     #[structopt(long = "remote", name = "remote_name", number_of_values = 1)]
     remotes: Vec<String>,
 
-    /// Repository path
+    /// Don't list branches from those remotes, even if selected with '--remote';  can be specified
+    /// multiple times
     #[structopt(
-        long = "repo-dir",
-        name = "path",
-        default_value = ".",
-        parse(from_os_str)
+        long = "ignore-remote",
+        name = "ignored_remote_name",
+        number_of_values = 1
     )]
-    repo_path: PathBuf,
-}
+    ignored_remotes: Vec<String>,
 
-const BRANCH_CHARACTERS_COUNT: usize = 16;
+    /// Only list branches with a name matching this pattern, where '*' matches any sequence of
+    /// characters and '?' a single character;  can be specified multiple times
+    #[structopt(long = "pattern", name = "pattern", number_of_values = 1)]
+    patterns: Vec<String>,
 
-fn number_size(mut n: usize) -> usize {
-    let mut result = 1;
-    while n >= 10 {
-        result += 1;
-        n /= 10;
-    }
-    result
-}
+    /// Show branches with a name matching this pattern in bold, without hiding the others;  uses
+    /// the same syntax as '--pattern' and can be specified multiple times
+    #[structopt(
+        long = "highlight",
+        name = "highlight_pattern",
+        value_name = "pattern",
+        number_of_values = 1
+    )]
+    highlight_patterns: Vec<String>,
 
-fn branch_size(commits_count: usize, max_commits_count: usize) -> (usize, bool) {
-    let ratio = commits_count as f64 / max_commits_count as f64;
-    let floating_size =
-        (ratio * std::f64::consts::PI / 2.).sin().sqrt() * BRANCH_CHARACTERS_COUNT as f64;
-    // let floating_size = (1. - (1. - ratio).powf(4.)) * BRANCH_CHARACTERS_COUNT as f64;
-    let floating_part = floating_size - floating_size.floor();
-    (
-        floating_size.ceil() as usize,
-        floating_part > 0. && floating_part <= 0.5,
-    )
-}
+    /// Only list branches for which this expression is true, like 'ahead > 5 && behind == 0';
+    /// available fields are 'ahead', 'behind' and 'age_days'
+    #[structopt(long = "filter-expr", name = "expression", parse(try_from_str))]
+    filter_expr: Option<Expr>,
 
-struct FormatedBranch {
-    last_commit_time: i64,
-    name: String,
-    remote: Option<String>,
-    behind: usize,
-    ahead: usize,
-}
+    /// Sort branches by the value of this expression, in descending order, like 'ahead + behind';
+    /// available fields are the same as for '--filter-expr'
+    #[structopt(
+        long = "sort-by-expr",
+        name = "sort_expression",
+        value_name = "expression",
+        parse(try_from_str)
+    )]
+    sort_expr: Option<Expr>,
 
-impl FormatedBranch {
-    fn from_branch(
-        repo: &Repository,
-        branch: &Branch,
-        opt: &Opt,
-        default_target: Oid,
-    ) -> Option<Self> {
-        let full_name = branch.get().name()?;
+    /// Keep branches in the order git enumerates them instead of sorting them by date and name
+    #[structopt(long = "no-sort")]
+    no_sort: bool,
 
-        let (name, remote) = if full_name.starts_with("refs/remotes/") {
-            let mut parts = full_name.splitn(4, '/');
-            let remote_name = parts.nth(2)?.into();
+    /// Only list branches named on the standard input, one per line, like the output of
+    /// 'git branch --merged';  '-r' and '-a' only show the remote column then
+    #[structopt(long = "branches-from-stdin")]
+    branches_from_stdin: bool,
 
-            // Only keep selected remotes, if needed
-            if !opt.remotes.is_empty() && !opt.remotes.contains(&remote_name) {
-                return None;
-            }
+    /// Only list branches with a tip reachable from this revision, meaning they are merged into it
+    #[structopt(long = "merged-into", name = "merged_into", value_name = "revision")]
+    merged_into: Option<String>,
 
-            (parts.next()?.into(), Some(remote_name))
-        } else if full_name.starts_with("refs/heads/") {
-            (full_name[11..].into(), None)
-        } else {
-            return None;
-        };
+    /// Always list the current branch, marked with a '*', even if filters would exclude it
+    #[structopt(long = "include-current")]
+    include_current_branch: bool,
 
-        let target = if opt.compare_with_upstream_branches {
-            branch.upstream().ok()?.get().target()?
-        } else {
-            default_target
-        };
+    /// Never list the current branch;  has no effect when HEAD is detached
+    #[structopt(long = "exclude-current", conflicts_with = "include_current_branch")]
+    exclude_current_branch: bool,
 
-        let (ahead, behind) = repo
-            .graph_ahead_behind(branch.get().target()?, target)
-            .ok()?;
+    /// Remove this prefix from displayed branch names
+    #[structopt(long = "strip-prefix", name = "prefix")]
+    strip_prefix: Option<String>,
 
-        Some(Self {
-            last_commit_time: branch
-                .get()
-                .peel_to_commit()
-                .ok()?
-                .author()
-                .when()
-                .seconds(),
-            remote,
-            name,
-            behind,
-            ahead,
-        })
+    /// Show remote branch names with their remote, like 'origin/develop'
+    #[structopt(long = "no-remote-prefix-strip")]
+    keep_remote_prefix: bool,
+
+    /// Pad or truncate displayed branch names to exactly this width, so the table layout doesn't
+    /// depend on branch name lengths
+    #[structopt(long = "max-name-width", name = "name_width", value_name = "width")]
+    max_name_width: Option<usize>,
+
+    /// List branches with names that are not valid UTF-8, replacing invalid bytes and showing
+    /// them in italics, instead of skipping them;  such branches are not included in
+    /// '--print-delete-commands', '-z' and '--numeric-only' output
+    #[structopt(long = "utf8-safe")]
+    utf8_safe: bool,
+
+    /// Stop counting commits ahead and behind after this number of commits, to keep diverged
+    /// histories fast
+    #[structopt(
+        long = "compare-commit-count-cap",
+        name = "max_count",
+        parse(try_from_str = "parse_count_cap")
+    )]
+    count_cap: Option<usize>,
+
+    /// Only count merge commits ahead and behind, like the number of merged pull requests
+    #[structopt(long = "count-merges-only")]
+    count_merges_only: bool,
+
+    /// Don't count merge commits ahead and behind, like in squash or rebase workflows
+    #[structopt(long = "exclude-merges", conflicts_with = "count_merges_only")]
+    exclude_merges: bool,
+
+    /// How to treat branches sharing no commit with their base, where counts cover their whole
+    /// history:  'show' them as is, 'skip' them or 'mark' them as unrelated
+    #[structopt(
+        long = "treat-root-commit",
+        name = "mode",
+        default_value = "show",
+        parse(try_from_str)
+    )]
+    unrelated_mode: UnrelatedMode,
+
+    /// Print a hint when comparing with HEAD while the worktree has uncommitted changes, which are
+    /// not part of the overview
+    #[structopt(long = "check-worktree")]
+    check_worktree: bool,
+
+    /// Collapse remote branches with the same name pointing to the same commit into a single row
+    #[structopt(long = "dedupe")]
+    dedupe: bool,
+
+    /// Show the kind of each branch;  automatically hidden when all branches are of the same kind
+    #[structopt(long = "show-ref-type")]
+    show_ref_type: bool,
+
+    /// Don't show the remote column;  automatically done when all branches are from the same
+    /// remote
+    #[structopt(long = "hide-remote-label")]
+    hide_remote_label: bool,
+
+    /// Show the age of the last commit of each branch
+    #[structopt(long = "age")]
+    show_age: bool,
+
+    /// Show the date of the last commit of each branch, in the author time zone
+    #[structopt(long = "date")]
+    show_date: bool,
+
+    /// Format of dates, with '%Y', '%m', '%d', '%H', '%M', '%S', '%z' (like '+0200'), '%:z' (like
+    /// '+02:00') and '%%' specifiers
+    #[structopt(
+        long = "time-format",
+        name = "time_format",
+        value_name = "format",
+        default_value = "%Y-%m-%dT%H:%M:%S%:z",
+        parse(try_from_str = "parse_time_format")
+    )]
+    time_format: String,
+
+    /// Show a compact ahead/behind summary, like '↑2 ↓1'
+    #[structopt(long = "tracking-sign")]
+    show_tracking_sign: bool,
+
+    /// Show the hash of the last commit of each branch
+    #[structopt(long = "oid")]
+    show_oid: bool,
+
+    /// Number of characters of abbreviated hashes, between 4 and 40;  defaults to the 'core.abbrev'
+    /// git configuration, or 7
+    #[structopt(
+        long = "oid-length",
+        name = "length",
+        parse(try_from_str = "parse_oid_length")
+    )]
+    oid_length: Option<usize>,
+
+    /// Show full hashes instead of abbreviated ones
+    #[structopt(long = "oid-full", conflicts_with = "length")]
+    oid_full: bool,
+
+    /// Output format: 'table', 'json', 'csv', 'tsv', 'markdown' or 'html'
+    #[structopt(
+        long = "format",
+        name = "format",
+        default_value = "table",
+        parse(try_from_str)
+    )]
+    format: OutputFormat,
+
+    /// Print each branch using the template read from this file, where placeholders like '{name}'
+    /// are replaced by column values ('{behind}' and '{ahead}' for the chart) and '{{' and '}}'
+    /// are literal braces;  overrides '--format'
+    #[structopt(
+        long = "template-file",
+        name = "template_path",
+        parse(from_os_str),
+        conflicts_with = "collapse_synced"
+    )]
+    template_file: Option<PathBuf>,
+
+    /// Pad '{name}' template placeholders to the display width of the longest listed name, so
+    /// what follows them is aligned
+    #[structopt(long = "name-width-from-longest", requires = "template_path")]
+    name_width_from_longest: bool,
+
+    /// Group branches by 'remote' or by name 'prefix' (the part before the first '/')
+    #[structopt(long = "group-by", name = "group_key", parse(try_from_str))]
+    group_by: Option<GroupBy>,
+
+    /// Color branch names by 'author' of their last commit, the same author always getting the
+    /// same color
+    #[structopt(long = "color-by", name = "color_key", parse(try_from_str))]
+    color_by: Option<ColorBy>,
+
+    /// Comma separated list of remotes, in the order their groups are shown with
+    /// '--group-by remote';  other remotes come after them, in alphabetical order
+    #[structopt(
+        long = "remote-sort",
+        name = "ordered_remote",
+        value_name = "remote",
+        raw(use_delimiter = "true")
+    )]
+    remote_order: Vec<String>,
+
+    /// Print the number of branches and their total commits ahead and behind after each group
+    #[structopt(long = "group-summary", requires = "group_key")]
+    group_summary: bool,
+
+    /// Comma separated list of columns to show, in order, among 'ref-type', 'remote', 'name', 'age',
+    /// 'date', 'tracking-sign', 'oid', 'subject', 'note', 'signature', 'sync', 'percent',
+    /// 'behind-authors', 'ahead-behind' and 'upstream-ahead-behind';  overrides other column
+    /// options
+    #[structopt(
+        long = "columns",
+        name = "column",
+        raw(use_delimiter = "true"),
+        parse(try_from_str)
+    )]
+    columns: Vec<Column>,
+
+    /// Comma separated list of columns to remove from the default ones, among the same names as
+    /// '--columns';  ignored with '--columns'
+    #[structopt(
+        long = "hide-columns",
+        name = "hidden_column",
+        value_name = "column",
+        raw(use_delimiter = "true"),
+        parse(try_from_str)
+    )]
+    hidden_columns: Vec<Column>,
+
+    /// Show whether the last commit of each branch is signed;  the signature is not verified
+    #[structopt(long = "show-signature")]
+    show_signature: bool,
+
+    /// Show the first line of the message of the last commit of each branch
+    #[structopt(long = "show-subject")]
+    show_subject: bool,
+
+    /// Show the first line of the git note attached to the last commit of each branch
+    #[structopt(long = "show-note")]
+    show_note: bool,
+
+    /// Notes reference to read notes from;  defaults to the 'core.notesRef' git configuration, or
+    /// 'refs/notes/commits'
+    #[structopt(long = "notes-ref", name = "notes_ref", value_name = "ref")]
+    notes_ref: Option<String>,
+
+    /// Show whether each local branch needs to be pushed or pulled, compared to its upstream
+    #[structopt(long = "local-remote-diff-column")]
+    show_sync: bool,
+
+    /// Show a second chart of commits behind and ahead of the upstream of each local branch, next
+    /// to the one compared with the base;  blank for branches without upstream
+    #[structopt(
+        long = "local-upstream-both",
+        conflicts_with = "compare_with_upstream_branches"
+    )]
+    show_upstream_chart: bool,
+
+    /// Show the share of commits ahead among commits ahead and behind, as a percentage
+    #[structopt(long = "show-percent")]
+    show_percent: bool,
+
+    /// Show the authors of the commits each branch is behind, most recent first;  with
+    /// '--compare-commit-count-cap', only the authors of the most recent commits are listed
+    #[structopt(long = "show-behind-authors")]
+    show_behind_authors: bool,
+
+    /// Chart style: 'split' draws commits behind and ahead on each side of a middle bar, 'stacked'
+    /// draws them one after the other, 'ratio' fills the whole bar with their proportions,
+    /// regardless of counts, in two colors in a terminal
+    #[structopt(
+        long = "chart-style",
+        name = "style",
+        default_value = "split",
+        parse(try_from_str)
+    )]
+    chart_style: ChartStyle,
+
+    /// Draw commits ahead on the left and commits behind on the right of the chart, with signed
+    /// counts
+    #[structopt(long = "reverse-bars")]
+    reverse_bars: bool,
+
+    /// Table style: 'minimal' separates columns, 'bordered' draws an ASCII box around the table
+    /// and 'clean' only aligns columns
+    #[structopt(
+        long = "table-style",
+        name = "table_style",
+        value_name = "style",
+        default_value = "minimal",
+        parse(try_from_str)
+    )]
+    table_style: TableStyle,
+
+    /// Only use ASCII characters to draw the table;  automatically enabled on legacy Windows
+    /// consoles
+    #[structopt(long = "ascii")]
+    ascii: bool,
+
+    /// Character separating table columns;  defaults to '·', or '|' with '--ascii' or
+    /// '--table-style bordered'
+    #[structopt(long = "separator", name = "char")]
+    separator: Option<char>,
+
+    /// Replace branches in sync with their base by a single line counting them, in the table
+    #[structopt(long = "collapse-synced")]
+    collapse_synced: bool,
+
+    /// Explain the chart below the table
+    #[structopt(long = "legend")]
+    legend: bool,
+
+    /// Print one short aligned line per branch, like 'feature-x  -3 │ +5  (2d)', instead of the
+    /// table
+    #[structopt(long = "compact")]
+    compact: bool,
+
+    /// Shrink the chart so the table fits in this number of columns, instead of the terminal width
+    #[structopt(long = "output-width", name = "columns_count", value_name = "width")]
+    output_width: Option<usize>,
+
+    /// Number of spaces on each side of table cells, between 0 and 8
+    #[structopt(
+        long = "pad",
+        name = "spaces",
+        default_value = "1",
+        parse(try_from_str = "parse_padding")
+    )]
+    padding: usize,
+
+    /// Exit with an error if no branch is listed
+    #[structopt(long = "fail-on-empty", conflicts_with = "empty_ok")]
+    fail_on_empty: bool,
+
+    /// Don't print any message if no branch is listed
+    #[structopt(long = "empty-ok")]
+    empty_ok: bool,
+
+    /// Exit with an error listing listed branches with a last commit older than this number of
+    /// days, after printing them
+    #[structopt(long = "fail-on-stale", name = "days")]
+    fail_on_stale: Option<u64>,
+
+    /// Report listed branches with different names pointing at the same commit, like forgotten
+    /// copies, on the standard error
+    #[structopt(long = "detect-duplicates")]
+    detect_duplicates: bool,
+
+    /// Report failed comparisons with the hashes of the compared commits, including comparisons
+    /// with upstream branches
+    #[structopt(short = "v", long = "verbose")]
+    verbose: bool,
+
+    /// Print git commands equivalent to what is computed on the standard error before the
+    /// overview
+    #[structopt(long = "explain")]
+    explain: bool,
+
+    /// Only print branch names, separated by NUL characters
+    #[structopt(short = "z", long = "null")]
+    null_terminated: bool,
+
+    /// Only print branch names and their commit counts behind and ahead, separated by tabs, one
+    /// branch per line;  capped counts end with '+', and counts are empty if comparing failed
+    #[structopt(long = "numeric-only")]
+    numeric_only: bool,
+
+    /// Only print the git commands deleting merged branches (branches whose tip is reachable from
+    /// their base, or all listed branches with '--merged-into');  nothing is deleted, and base
+    /// branches, their remote counterparts, the default branch of each remote, branches at the
+    /// base commit and the current branch are never included
+    #[structopt(long = "print-delete-commands")]
+    print_delete_commands: bool,
+
+    /// Only print the number of branches that would be listed
+    #[structopt(long = "count")]
+    count: bool,
+
+    /// Only print aggregate statistics about the branches that would be listed, as a JSON object
+    #[structopt(long = "summary-json", conflicts_with = "count")]
+    summary_json: bool,
+
+    /// Clear the screen and refresh the overview every <seconds> (default: 5), given as
+    /// '--watch=<seconds>'
+    #[structopt(
+        long = "watch",
+        value_name = "seconds",
+        parse(try_from_str = "parse_watch_interval"),
+        raw(min_values = "0", require_equals = "true")
+    )]
+    watch: Option<u64>,
+
+    /// Repository path;  defaults to the repository found from the current directory, honoring
+    /// the GIT_DIR and GIT_WORK_TREE environment variables
+    #[structopt(long = "repo-dir", name = "path", parse(from_os_str))]
+    repo_path: Option<PathBuf>,
+
+    /// Print a completion script for this shell ('bash', 'zsh', 'fish', 'powershell' or 'elvish')
+    #[structopt(
+        long = "generate-completion",
+        name = "shell",
+        parse(try_from_str),
+        raw(hidden = "true")
+    )]
+    generate_completion: Option<Shell>,
+
+    /// Only print the names of local and remote branches, for shell completion
+    #[structopt(long = "list-branch-names", raw(hidden = "true"))]
+    list_branch_names: bool,
+
+    /// Only print the effective options, once implied ones are applied, as a JSON object, without
+    /// opening the repository
+    #[structopt(long = "config-dump")]
+    config_dump: bool,
+}
+
+const DEFAULT_OID_LENGTH: usize = 7;
+
+fn parse_oid_length(length: &str) -> Result<usize, String> {
+    match length.parse() {
+        Ok(length) if (4..=40).contains(&length) => Ok(length),
+        _ => Err(format!(
+            "expected a number between 4 and 40, got '{}'",
+            length
+        )),
     }
+}
 
-    fn format_chart_line(&self, max: usize) -> String {
-        let mut result = String::new();
+fn parse_count_cap(count: &str) -> Result<usize, String> {
+    match count.parse() {
+        Ok(count) if count >= 1 => Ok(count),
+        _ => Err(format!("expected a positive number, got '{}'", count)),
+    }
+}
 
-        // First half
-        {
-            let (behind_size, behind_half) = branch_size(self.behind, max);
+fn parse_watch_interval(seconds: &str) -> Result<u64, String> {
+    match seconds.parse() {
+        Ok(seconds) if seconds >= 1 => Ok(seconds),
+        _ => Err(format!(
+            "expected a positive number of seconds, got '{}'",
+            seconds
+        )),
+    }
+}
 
-            result.extend(repeat(' ').take(
-                BRANCH_CHARACTERS_COUNT + number_size(max) - number_size(self.behind) - behind_size,
-            ));
+fn parse_padding(spaces: &str) -> Result<usize, String> {
+    match spaces.parse() {
+        Ok(spaces) if spaces <= 8 => Ok(spaces),
+        _ => Err(format!(
+            "expected a number between 0 and 8, got '{}'",
+            spaces
+        )),
+    }
+}
 
-            write!(result, "{} ", self.behind).unwrap();
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Column {
+    RefType,
+    Remote,
+    Name,
+    Age,
+    Date,
+    TrackingSign,
+    Oid,
+    Subject,
+    Note,
+    Signature,
+    Sync,
+    Percent,
+    BehindAuthors,
+    AheadBehind,
+    UpstreamAheadBehind,
+}
 
-            if behind_half {
-                result.push('╺');
-                result.extend(repeat('━').take(behind_size - 1));
-            } else {
-                result.extend(repeat('━').take(behind_size));
-            }
+impl Column {
+    fn name(self) -> &'static str {
+        match self {
+            Column::RefType => "ref-type",
+            Column::Remote => "remote",
+            Column::Name => "name",
+            Column::Age => "age",
+            Column::Date => "date",
+            Column::TrackingSign => "tracking-sign",
+            Column::Oid => "oid",
+            Column::Subject => "subject",
+            Column::Note => "note",
+            Column::Signature => "signature",
+            Column::Sync => "sync",
+            Column::Percent => "percent",
+            Column::BehindAuthors => "behind-authors",
+            Column::AheadBehind => "ahead-behind",
+            Column::UpstreamAheadBehind => "upstream-ahead-behind",
         }
+    }
 
-        // Middle bar
-        result.push(if self.behind == 0 && self.ahead == 0 {
-            '│'
-        } else if self.behind == 0 {
-            '┝'
-        } else if self.ahead == 0 {
-            '┥'
-        } else {
-            '┿'
-        });
+    /// Whether the column is drawn as a chart in tables
+    fn is_chart(self) -> bool {
+        matches!(self, Column::AheadBehind | Column::UpstreamAheadBehind)
+    }
+}
 
-        // Second half
-        {
-            let (ahead_size, ahead_half) = branch_size(self.ahead, max);
+impl FromStr for Column {
+    type Err = String;
 
-            if ahead_half {
-                result.extend(repeat('━').take(ahead_size - 1));
-                result.push('╸');
-            } else {
-                result.extend(repeat('━').take(ahead_size));
-            }
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "ref-type" => Ok(Column::RefType),
+            "remote" => Ok(Column::Remote),
+            "name" => Ok(Column::Name),
+            "age" => Ok(Column::Age),
+            "date" => Ok(Column::Date),
+            "tracking-sign" => Ok(Column::TrackingSign),
+            "oid" => Ok(Column::Oid),
+            "subject" => Ok(Column::Subject),
+            "note" => Ok(Column::Note),
+            "signature" => Ok(Column::Signature),
+            "sync" => Ok(Column::Sync),
+            "percent" => Ok(Column::Percent),
+            "behind-authors" => Ok(Column::BehindAuthors),
+            "ahead-behind" => Ok(Column::AheadBehind),
+            "upstream-ahead-behind" => Ok(Column::UpstreamAheadBehind),
+            _ => Err(format!("unknown column '{}'", name)),
+        }
+    }
+}
 
-            write!(result, " {}", self.ahead).unwrap();
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+    Tsv,
+    Markdown,
+    Html,
+}
 
-            result.extend(repeat(' ').take(
-                number_size(max) - number_size(self.ahead) + BRANCH_CHARACTERS_COUNT - ahead_size,
-            ));
+impl OutputFormat {
+    fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Table => "table",
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Tsv => "tsv",
+            OutputFormat::Markdown => "markdown",
+            OutputFormat::Html => "html",
         }
-
-        result
     }
 }
 
-fn compare_branches(a: &FormatedBranch, b: &FormatedBranch) -> std::cmp::Ordering {
-    // Compare commit authoring date
-    b.last_commit_time
-        .cmp(&a.last_commit_time)
-        // Compare remotes
-        .then_with(|| match (a.remote.as_ref(), b.remote.as_ref()) {
-            (Some(remote_a), Some(remote_b)) => remote_a.cmp(remote_b),
-            (None, Some(_)) => std::cmp::Ordering::Less,
-            (Some(_), None) => std::cmp::Ordering::Greater,
-            (None, None) => std::cmp::Ordering::Equal,
-        })
-        // Compare names
-        .then_with(|| a.name.cmp(&b.name))
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "tsv" => Ok(OutputFormat::Tsv),
+            "markdown" => Ok(OutputFormat::Markdown),
+            "html" => Ok(OutputFormat::Html),
+            _ => Err(format!("unknown format '{}'", name)),
+        }
+    }
 }
 
-#[derive(Debug)]
-enum CliError {
-    GitError(git2::Error),
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ChartStyle {
+    Split,
+    Stacked,
+    Ratio,
 }
 
-impl From<git2::Error> for CliError {
-    fn from(error: git2::Error) -> Self {
-        CliError::GitError(error)
+impl ChartStyle {
+    fn name(self) -> &'static str {
+        match self {
+            ChartStyle::Split => "split",
+            ChartStyle::Stacked => "stacked",
+            ChartStyle::Ratio => "ratio",
+        }
     }
 }
 
-fn run() -> Result<(), CliError> {
-    let mut opt = Opt::from_args();
+impl FromStr for ChartStyle {
+    type Err = String;
 
-    if !opt.remotes.is_empty() {
-        opt.remote_branches = true;
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "split" => Ok(ChartStyle::Split),
+            "stacked" => Ok(ChartStyle::Stacked),
+            "ratio" => Ok(ChartStyle::Ratio),
+            _ => Err(format!("unknown chart style '{}'", name)),
+        }
     }
+}
 
-    let repo = Repository::open(&opt.repo_path)?;
-    let default_target = repo.revparse_single(&opt.base_revision)?.id();
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TableStyle {
+    Minimal,
+    Bordered,
+    Clean,
+}
 
-    let mut branches: Vec<_> = repo
-        .branches(
-            if opt.all_branches || (opt.remote_branches && opt.local_branches) {
-                None
-            } else if opt.remote_branches {
-                Some(BranchType::Remote)
-            } else {
-                Some(BranchType::Local)
-            },
-        )?
-        .filter_map(|result| {
-            FormatedBranch::from_branch(&repo, &result.ok()?.0, &opt, default_target)
-        })
-        .collect();
+impl TableStyle {
+    fn name(self) -> &'static str {
+        match self {
+            TableStyle::Minimal => "minimal",
+            TableStyle::Bordered => "bordered",
+            TableStyle::Clean => "clean",
+        }
+    }
+}
 
-    branches.sort_by(compare_branches);
+impl FromStr for TableStyle {
+    type Err = String;
 
-    let mut table = Table::new();
-    let mut format = TableFormat::new();
-    format.padding(1, 1);
-    format.column_separator('·');
-    table.set_format(format);
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "minimal" => Ok(TableStyle::Minimal),
+            "bordered" => Ok(TableStyle::Bordered),
+            "clean" => Ok(TableStyle::Clean),
+            _ => Err(format!("unknown table style '{}'", name)),
+        }
+    }
+}
 
-    let max = branches
-        .iter()
-        .map(|branch| branch.ahead.max(branch.behind))
-        .max()
-        .unwrap()
-        .max(1);
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum GroupBy {
+    Remote,
+    Prefix,
+}
 
-    for branch in branches.iter() {
-        let mut row = Vec::new();
-
-        if opt.all_branches || opt.remote_branches {
-            row.push(
-                Cell::new(branch.remote.as_ref().map_or("local", |remote| remote)).style_spec(
-                    if branch.remote.is_none() {
-                        "Fgb"
-                    } else {
-                        "Frb"
-                    },
-                ),
-            );
+impl GroupBy {
+    fn name(self) -> &'static str {
+        match self {
+            GroupBy::Remote => "remote",
+            GroupBy::Prefix => "prefix",
         }
-        row.push(Cell::new(&branch.name));
-        row.push(Cell::new(&branch.format_chart_line(max)));
+    }
+}
 
-        table.add_row(Row::new(row));
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "remote" => Ok(GroupBy::Remote),
+            "prefix" => Ok(GroupBy::Prefix),
+            _ => Err(format!("unknown group key '{}'", name)),
+        }
     }
+}
 
-    table.printstd();
-    Ok(())
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ColorBy {
+    Author,
 }
 
-fn main() {
-    run().unwrap_or_else(|error: CliError| {
-        let message = match error {
+impl ColorBy {
+    fn name(self) -> &'static str {
+        match self {
+            ColorBy::Author => "author",
+        }
+    }
+}
+
+impl FromStr for ColorBy {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "author" => Ok(ColorBy::Author),
+            _ => Err(format!("unknown color key '{}'", name)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum UnrelatedMode {
+    Show,
+    Skip,
+    Mark,
+}
+
+impl UnrelatedMode {
+    fn name(self) -> &'static str {
+        match self {
+            UnrelatedMode::Show => "show",
+            UnrelatedMode::Skip => "skip",
+            UnrelatedMode::Mark => "mark",
+        }
+    }
+}
+
+impl FromStr for UnrelatedMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "show" => Ok(UnrelatedMode::Show),
+            "skip" => Ok(UnrelatedMode::Skip),
+            "mark" => Ok(UnrelatedMode::Mark),
+            _ => Err(format!("unknown mode '{}'", name)),
+        }
+    }
+}
+
+impl Opt {
+    /// How merge commits are counted, from '--count-merges-only' and '--exclude-merges'
+    fn merges(&self) -> Merges {
+        if self.count_merges_only {
+            Merges::Only
+        } else if self.exclude_merges {
+            Merges::Excluded
+        } else {
+            Merges::Included
+        }
+    }
+
+    /// Columns to show, in order
+    fn columns(&self) -> Vec<Column> {
+        if !self.columns.is_empty() {
+            return self.columns.clone();
+        }
+
+        let mut columns = Vec::new();
+        if self.show_ref_type {
+            columns.push(Column::RefType);
+        }
+        if self.all_branches || self.remote_branches {
+            columns.push(Column::Remote);
+        }
+        columns.push(Column::Name);
+        if self.show_age {
+            columns.push(Column::Age);
+        }
+        if self.show_date {
+            columns.push(Column::Date);
+        }
+        if self.show_tracking_sign {
+            columns.push(Column::TrackingSign);
+        }
+        if self.show_oid {
+            columns.push(Column::Oid);
+        }
+        if self.show_subject {
+            columns.push(Column::Subject);
+        }
+        if self.show_note {
+            columns.push(Column::Note);
+        }
+        if self.show_signature {
+            columns.push(Column::Signature);
+        }
+        if self.show_sync {
+            columns.push(Column::Sync);
+        }
+        if self.show_percent {
+            columns.push(Column::Percent);
+        }
+        if self.show_behind_authors {
+            columns.push(Column::BehindAuthors);
+        }
+        columns.push(Column::AheadBehind);
+        if self.show_upstream_chart {
+            columns.push(Column::UpstreamAheadBehind);
+        }
+        columns.retain(|column| !self.hidden_columns.contains(column));
+        columns
+    }
+}
+
+#[derive(Debug)]
+struct BaseRule {
+    pattern: String,
+    revision: String,
+}
+
+fn parse_base_rule(rule: &str) -> Result<BaseRule, String> {
+    let mut parts = rule.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(pattern), Some(revision)) if !pattern.is_empty() && !revision.is_empty() => {
+            Ok(BaseRule {
+                pattern: pattern.into(),
+                revision: revision.into(),
+            })
+        }
+        _ => Err(format!("expected '<pattern>=<revision>', got '{}'", rule)),
+    }
+}
+
+/// Match a name against a pattern where '*' matches any sequence of characters and '?' matches a
+/// single character
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = backtrack {
+            // Let the last '*' consume one more character
+            p = star_p + 1;
+            n = star_n + 1;
+            backtrack = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Revisions to compare branches with
+struct Bases {
+    default: Oid,
+    rules: Vec<(String, Oid)>,
+    /// Full names of the branches used as bases, like 'refs/heads/main', and of their remote
+    /// counterparts
+    branch_refs: Vec<String>,
+}
+
+/// Full name of the branch a revision names, following symbolic references like 'HEAD'
+fn branch_ref(repo: &Repository, revision: &str) -> Option<String> {
+    let reference = find_reference(repo, revision)?.resolve().ok()?;
+    Some(reference.name()?.to_string())
+        .filter(|name| name.starts_with("refs/heads/") || name.starts_with("refs/remotes/"))
+}
+
+/// Full names of the remote branches standing for local base branches: their upstream, the
+/// branches of the same name on each remote, and the default branch of each remote
+fn remote_counterparts(repo: &Repository, branch_refs: &[String]) -> Vec<String> {
+    let remotes: Vec<String> = repo.remotes().map_or(Vec::new(), |remotes| {
+        remotes.iter().flatten().map(String::from).collect()
+    });
+    let mut counterparts = Vec::new();
+    for name in branch_refs
+        .iter()
+        .filter_map(|full_name| full_name.strip_prefix("refs/heads/"))
+    {
+        if let Ok(branch) = repo.find_branch(name, BranchType::Local) {
+            if let Ok(upstream) = branch.upstream() {
+                counterparts.extend(upstream.get().name().map(String::from));
+            }
+        }
+        counterparts.extend(
+            remotes
+                .iter()
+                .map(|remote| format!("refs/remotes/{}/{}", remote, name)),
+        );
+    }
+    counterparts.extend(
+        remotes
+            .iter()
+            .filter_map(|remote| branch_ref(repo, &format!("refs/remotes/{}/HEAD", remote))),
+    );
+    counterparts
+}
+
+impl Bases {
+    fn resolve(repo: &Repository, opt: &Opt) -> Result<Self, CliError> {
+        let mut branch_refs: Vec<String> = opt
+            .base_rules
+            .iter()
+            .map(|rule| rule.revision.as_str())
+            .chain(Some(opt.base_revision.as_str()).filter(|_| {
+                opt.since_tag.is_none()
+                    && opt.relative_base.is_none()
+                    && opt.merge_base_revisions.is_empty()
+            }))
+            .filter_map(|revision| branch_ref(repo, revision))
+            .collect();
+        branch_refs.extend(remote_counterparts(repo, &branch_refs));
+
+        Ok(Self {
+            default: match (
+                opt.since_tag.as_ref(),
+                opt.relative_base,
+                opt.merge_base_revisions.as_slice(),
+            ) {
+                (Some(tag), _, _) => resolve_tag(repo, tag)?,
+                (None, Some(count), _) => resolve_relative_base(repo, count)?,
+                (None, None, [a, b]) => resolve_merge_base(repo, a, b)?,
+                (None, None, _) => resolve_revision(repo, &opt.base_revision)?,
+            },
+            rules: opt
+                .base_rules
+                .iter()
+                .map(|rule| {
+                    Ok((
+                        rule.pattern.clone(),
+                        resolve_revision(repo, &rule.revision)?,
+                    ))
+                })
+                .collect::<Result<_, CliError>>()?,
+            branch_refs,
+        })
+    }
+
+    fn for_branch(&self, name: &str) -> Oid {
+        self.rules
+            .iter()
+            .find(|(pattern, _)| matches_pattern(pattern, name))
+            .map_or(self.default, |&(_, target)| target)
+    }
+}
+
+/// Resolve the best common ancestor of two revisions
+fn resolve_merge_base(repo: &Repository, a: &str, b: &str) -> Result<Oid, CliError> {
+    match repo.merge_base(resolve_revision(repo, a)?, resolve_revision(repo, b)?) {
+        Ok(oid) => Ok(oid),
+        Err(ref error) if error.code() == ErrorCode::NotFound => {
+            Err(CliError::NoMergeBase(a.into(), b.into()))
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Find the reference named by `name`, in the order git looks references up
+fn find_reference<'a>(repo: &'a Repository, name: &str) -> Option<git2::Reference<'a>> {
+    let candidates = [
+        name.to_string(),
+        format!("refs/{}", name),
+        format!("refs/tags/{}", name),
+        format!("refs/heads/{}", name),
+        format!("refs/remotes/{}", name),
+        format!("refs/remotes/{}/HEAD", name),
+    ];
+    candidates
+        .iter()
+        .find_map(|name| repo.find_reference(name).ok())
+}
+
+/// Resolve a revision to the commit it points to
+fn resolve_revision(repo: &Repository, revision: &str) -> Result<Oid, CliError> {
+    // git silently prefers the tag when a branch has the same name
+    if repo
+        .find_reference(&format!("refs/heads/{}", revision))
+        .is_ok()
+        && repo
+            .find_reference(&format!("refs/tags/{}", revision))
+            .is_ok()
+    {
+        return Err(CliError::AmbiguousRevision(revision.into()));
+    }
+
+    // Follow symbolic references like 'origin/HEAD' explicitly
+    if let Some(reference) = find_reference(repo, revision) {
+        return Ok(reference.resolve()?.peel_to_commit()?.id());
+    }
+
+    // Reflog entries like 'main@{5}' that don't exist would be reported as unknown revisions
+    if let Some((name, index)) = revision
+        .strip_suffix('}')
+        .and_then(|revision| revision.rsplit_once("@{"))
+        .and_then(|(name, index)| Some((name, index.parse::<usize>().ok()?)))
+    {
+        let reference = match name {
+            "" => repo.head().ok(),
+            name => find_reference(repo, name),
+        };
+        if let Some(reference) = reference {
+            let length = reference
+                .name()
+                .and_then(|name| repo.reflog(name).ok())
+                .map_or(0, |reflog| reflog.len());
+            if index >= length {
+                let name = reference.shorthand().unwrap_or(name);
+                return Err(CliError::ReflogTooShort(name.into(), index, length));
+            }
+        }
+    }
+
+    match repo.revparse_single(revision) {
+        Ok(object) => Ok(object.peel_to_commit()?.id()),
+        Err(ref error) if error.code() == ErrorCode::NotFound => Err(CliError::UnknownRevision(
+            revision.into(),
+            suggest_revisions(repo, revision)?,
+        )),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Resolve a tag to the commit it points to
+fn resolve_tag(repo: &Repository, tag: &str) -> Result<Oid, CliError> {
+    match repo.find_reference(&format!("refs/tags/{}", tag)) {
+        Ok(reference) => Ok(reference.peel_to_commit()?.id()),
+        Err(ref error) if error.code() == ErrorCode::NotFound => {
+            Err(CliError::UnknownTag(tag.into()))
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Walk `count` first-parents back from HEAD
+fn resolve_relative_base(repo: &Repository, count: usize) -> Result<Oid, CliError> {
+    let mut commit = repo.head()?.peel_to_commit()?;
+    for walked in 0..count {
+        commit = match commit.parent(0) {
+            Ok(parent) => parent,
+            Err(ref error) if error.code() == ErrorCode::NotFound => {
+                return Err(CliError::HistoryTooShort(count, walked));
+            }
+            Err(error) => return Err(error.into()),
+        };
+    }
+    Ok(commit.id())
+}
+
+/// List local and remote branches and tags sharing the last component of an unknown revision, or
+/// else the closest ones to catch typos
+fn suggest_revisions(repo: &Repository, revision: &str) -> Result<Vec<String>, git2::Error> {
+    let mut names: Vec<String> = repo
+        .branches(None)?
+        .filter_map(|result| result.ok()?.0.name().ok()?.map(String::from))
+        .collect();
+    names.extend(repo.tag_names(None)?.iter().flatten().map(String::from));
+
+    let needle = revision.rsplit('/').next().unwrap_or(revision);
+    let mut suggestions: Vec<String> = names
+        .iter()
+        .filter(|name| name.contains(needle))
+        .cloned()
+        .collect();
+
+    if suggestions.is_empty() {
+        let max_distance = revision.chars().count() / 3 + 1;
+        let distances: Vec<(usize, &String)> = names
+            .iter()
+            .map(|name| (edit_distance(revision, name), name))
+            .collect();
+        if let Some(min_distance) = distances
+            .iter()
+            .map(|&(distance, _)| distance)
+            .min()
+            .filter(|&distance| distance <= max_distance)
+        {
+            suggestions = distances
+                .into_iter()
+                .filter(|&(distance, _)| distance == min_distance)
+                .map(|(_, name)| name.clone())
+                .collect();
+        }
+    }
+
+    suggestions.sort();
+    suggestions.dedup();
+    Ok(suggestions)
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            current.push(
+                (previous[j] + usize::from(a_char != *b_char))
+                    .min(previous[j + 1] + 1)
+                    .min(current[j] + 1),
+            );
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+const BRANCH_CHARACTERS_COUNT: usize = 16;
+
+/// Narrowest chart half when shrinking the table to fit the terminal
+const MIN_BRANCH_CHARACTERS_COUNT: usize = 4;
+
+/// Width of commit subjects in the table, like the length git recommends for them
+const SUBJECT_WIDTH: usize = 50;
+
+/// Number of authors listed in the 'behind-authors' column before summarizing the others
+const MAX_BEHIND_AUTHORS: usize = 3;
+
+/// Colors of branch names with '--color-by', red being left out as it signals errors
+const NAME_COLORS: [&str; 8] = ["Fb", "Fm", "Fc", "Fy", "Fg", "FB", "FM", "FC"];
+
+/// Characters used to draw the table
+struct Glyphs {
+    column_separator: char,
+    bar: char,
+    behind_half_bar: char,
+    ahead_half_bar: char,
+    middle: char,
+    middle_ahead: char,
+    middle_behind: char,
+    middle_diverged: char,
+    stacked_behind_bar: char,
+    stacked_ahead_bar: char,
+    synced_sign: &'static str,
+    ahead_sign: &'static str,
+    behind_sign: &'static str,
+    ellipsis: &'static str,
+    missing: &'static str,
+    /// ANSI escape sequences coloring commits behind and ahead in ratio charts, if colors are
+    /// enabled
+    ratio_colors: Option<(&'static str, &'static str)>,
+}
+
+const UNICODE_GLYPHS: Glyphs = Glyphs {
+    column_separator: '·',
+    bar: '━',
+    behind_half_bar: '╺',
+    ahead_half_bar: '╸',
+    middle: '│',
+    middle_ahead: '┝',
+    middle_behind: '┥',
+    middle_diverged: '┿',
+    stacked_behind_bar: '╍',
+    stacked_ahead_bar: '━',
+    synced_sign: "✓",
+    ahead_sign: "↑",
+    behind_sign: "↓",
+    ellipsis: "…",
+    missing: "—",
+    ratio_colors: None,
+};
+
+const ASCII_GLYPHS: Glyphs = Glyphs {
+    column_separator: '|',
+    bar: '=',
+    behind_half_bar: '-',
+    ahead_half_bar: '-',
+    middle: '|',
+    middle_ahead: '|',
+    middle_behind: '|',
+    middle_diverged: '|',
+    stacked_behind_bar: '-',
+    stacked_ahead_bar: '=',
+    synced_sign: "=",
+    ahead_sign: "+",
+    behind_sign: "-",
+    ellipsis: "...",
+    missing: "-",
+    ratio_colors: None,
+};
+
+/// Colors of commits behind and ahead in ratio charts, matching the 'pull' and 'push' sync states
+const RATIO_COLORS: (&str, &str) = ("\x1b[36m", "\x1b[33m");
+const DEFAULT_COLOR: &str = "\x1b[39m";
+
+/// Pad `text` with spaces or truncate it with an ellipsis to be exactly `width` columns wide
+fn fit_width(text: &str, width: usize, ellipsis: &str) -> String {
+    let mut result = String::new();
+    if text.width() <= width {
+        result.push_str(text);
+    } else {
+        let ellipsis = if ellipsis.width() < width {
+            ellipsis
+        } else {
+            ""
+        };
+        let mut result_width = 0;
+        for c in text.chars() {
+            let char_width = c.width().unwrap_or(0);
+            if result_width + char_width + ellipsis.width() > width {
+                break;
+            }
+            result.push(c);
+            result_width += char_width;
+        }
+        result.push_str(ellipsis);
+    }
+    let padding = width - result.width();
+    result.extend(repeat(' ').take(padding));
+    result
+}
+
+const HOUR: i64 = 60 * 60;
+const DAY: i64 = 24 * HOUR;
+const WEEK: i64 = 7 * DAY;
+const MONTH: i64 = 30 * DAY;
+const YEAR: i64 = 365 * DAY;
+
+/// Quote a string to be used as a single shell argument
+fn shell_quote(value: &str) -> String {
+    if !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./@+:,".contains(c))
+    {
+        value.into()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() as i64)
+}
+
+fn format_age(age: i64) -> String {
+    let (count, unit) = if age < HOUR {
+        (age / 60, "minute")
+    } else if age < DAY {
+        (age / HOUR, "hour")
+    } else if age < WEEK {
+        (age / DAY, "day")
+    } else if age < MONTH {
+        (age / WEEK, "week")
+    } else if age < YEAR {
+        (age / MONTH, "month")
+    } else {
+        (age / YEAR, "year")
+    };
+    format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+}
+
+/// Format an age with a single unit letter, like '2d'
+fn format_short_age(age: i64) -> String {
+    if age < HOUR {
+        format!("{}m", age / 60)
+    } else if age < DAY {
+        format!("{}h", age / HOUR)
+    } else if age < WEEK {
+        format!("{}d", age / DAY)
+    } else if age < MONTH {
+        format!("{}w", age / WEEK)
+    } else if age < YEAR {
+        format!("{}mo", age / MONTH)
+    } else {
+        format!("{}y", age / YEAR)
+    }
+}
+
+fn parse_time_format(format: &str) -> Result<String, String> {
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c == '%' {
+            match chars.next() {
+                Some('Y' | 'm' | 'd' | 'H' | 'M' | 'S' | 'z' | '%') => {}
+                Some(':') if chars.next() == Some('z') => {}
+                Some(specifier) => return Err(format!("unknown specifier '%{}'", specifier)),
+                None => return Err("incomplete specifier at the end of the format".into()),
+            }
+        }
+    }
+    Ok(format.into())
+}
+
+/// Format a timestamp shifted by a time zone offset, with a format checked by `parse_time_format`
+fn format_time(time: i64, offset_minutes: i32, format: &str) -> String {
+    let local = time + i64::from(offset_minutes) * 60;
+    let (days, seconds) = (local.div_euclid(DAY), local.rem_euclid(DAY));
+
+    // Convert days since the epoch to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    let sign = if offset_minutes < 0 { '-' } else { '+' };
+    let (offset_hours, offset_minutes) = (offset_minutes.abs() / 60, offset_minutes.abs() % 60);
+
+    let mut result = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => write!(result, "{:04}", year),
+            Some('m') => write!(result, "{:02}", month),
+            Some('d') => write!(result, "{:02}", day),
+            Some('H') => write!(result, "{:02}", seconds / HOUR),
+            Some('M') => write!(result, "{:02}", seconds % HOUR / 60),
+            Some('S') => write!(result, "{:02}", seconds % 60),
+            Some('z') => write!(result, "{}{:02}{:02}", sign, offset_hours, offset_minutes),
+            Some(':') => {
+                chars.next();
+                write!(result, "{}{:02}:{:02}", sign, offset_hours, offset_minutes)
+            }
+            _ => write!(result, "%"),
+        }
+        .unwrap();
+    }
+    result
+}
+
+/// Color of a key, using a FNV-1a hash so it doesn't change between runs
+fn key_style_spec(key: &str) -> &'static str {
+    let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    NAME_COLORS[(hash % NAME_COLORS.len() as u64) as usize]
+}
+
+fn age_style_spec(age: i64) -> &'static str {
+    if age < DAY {
+        "Fg"
+    } else if age < WEEK {
+        ""
+    } else if age < MONTH {
+        "Fy"
+    } else {
+        "Fr"
+    }
+}
+
+fn number_size(mut n: usize) -> usize {
+    let mut result = 1;
+    while n >= 10 {
+        result += 1;
+        n /= 10;
+    }
+    result
+}
+
+fn count_label(count: usize, capped: bool) -> String {
+    if capped {
+        format!("{}+", count)
+    } else {
+        count.to_string()
+    }
+}
+
+/// Which commits are counted ahead and behind
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Merges {
+    Included,
+    Only,
+    Excluded,
+}
+
+/// Count commits reachable from `from` but not from `hidden`, stopping after `cap` commits.
+/// Return the count and whether it was capped.
+fn count_commits(
+    repo: &Repository,
+    from: Oid,
+    hidden: Oid,
+    cap: usize,
+    merges: Merges,
+) -> Result<(usize, bool), git2::Error> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(from)?;
+    revwalk.hide(hidden)?;
+
+    let mut count = 0;
+    for oid in revwalk {
+        let oid = oid?;
+        if merges != Merges::Included
+            && (merges == Merges::Only) != (repo.find_commit(oid)?.parent_ids().len() > 1)
+        {
+            continue;
+        }
+        if count == cap {
+            return Ok((count, true));
+        }
+        count += 1;
+    }
+    Ok((count, false))
+}
+
+/// Distinct authors of the commits reachable from `from` but not from `hidden`, most recent first,
+/// stopping after `cap` commits.  Return the authors and whether the walk was capped.
+fn commit_authors(
+    repo: &Repository,
+    from: Oid,
+    hidden: Oid,
+    cap: usize,
+) -> Result<(Vec<String>, bool), git2::Error> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(from)?;
+    revwalk.hide(hidden)?;
+
+    let mut authors = Vec::new();
+    for (index, oid) in revwalk.enumerate() {
+        if index == cap {
+            return Ok((authors, true));
+        }
+        let commit = repo.find_commit(oid?)?;
+        let author = String::from_utf8_lossy(commit.author().name_bytes()).into_owned();
+        if !authors.contains(&author) {
+            authors.push(author);
+        }
+    }
+    Ok((authors, false))
+}
+
+fn branch_size(commits_count: usize, max_commits_count: usize, bar_width: usize) -> (usize, bool) {
+    let ratio = commits_count as f64 / max_commits_count as f64;
+    let floating_size = (ratio * std::f64::consts::PI / 2.).sin().sqrt() * bar_width as f64;
+    // let floating_size = (1. - (1. - ratio).powf(4.)) * bar_width as f64;
+    let floating_part = floating_size - floating_size.floor();
+    (
+        floating_size.ceil() as usize,
+        floating_part > 0. && floating_part <= 0.5,
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RefKind {
+    Local,
+    Remote,
+}
+
+impl RefKind {
+    fn name(self) -> &'static str {
+        match self {
+            RefKind::Local => "local",
+            RefKind::Remote => "remote",
+        }
+    }
+
+    fn style_spec(self) -> &'static str {
+        match self {
+            RefKind::Local => "Fg",
+            RefKind::Remote => "Fr",
+        }
+    }
+}
+
+/// What to do to bring a local branch in line with its upstream
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SyncState {
+    Synced,
+    Push,
+    Pull,
+    Diverged,
+}
+
+impl SyncState {
+    fn name(self) -> &'static str {
+        match self {
+            SyncState::Synced => "synced",
+            SyncState::Push => "push",
+            SyncState::Pull => "pull",
+            SyncState::Diverged => "diverged",
+        }
+    }
+
+    fn style_spec(self) -> &'static str {
+        match self {
+            SyncState::Synced => "Fg",
+            SyncState::Push => "Fy",
+            SyncState::Pull => "Fc",
+            SyncState::Diverged => "Fr",
+        }
+    }
+}
+
+#[derive(Clone)]
+struct FormatedBranch {
+    kind: RefKind,
+    last_commit_time: i64,
+    /// Time zone offset of the last commit author, in minutes
+    last_commit_offset: i32,
+    /// First line of the message of the last commit
+    tip_summary: String,
+    /// Name of the author of the last commit
+    tip_author: String,
+    /// First line of the note attached to the last commit, if requested
+    note: Option<String>,
+    /// Name of the branch as understood by git commands, like 'origin/master'
+    shorthand: String,
+    name: String,
+    remote: Option<String>,
+    /// Remotes having this branch at the same commit, more than one with '--dedupe'
+    remotes: Vec<String>,
+    /// Whether this is the currently checked out branch
+    is_head: bool,
+    /// Whether invalid UTF-8 sequences were replaced in the name
+    lossy_name: bool,
+    tip: Oid,
+    /// Whether the tip commit has a signature, if requested
+    signed: bool,
+    /// State compared to the upstream, if requested and the branch has one
+    sync: Option<SyncState>,
+    /// Commits ahead and behind the upstream, if requested and the branch has one
+    upstream_divergence: Option<(usize, usize)>,
+    behind: usize,
+    /// Whether counting commits behind stopped before reaching the merge base
+    behind_capped: bool,
+    ahead: usize,
+    /// Whether counting commits ahead stopped before reaching the merge base
+    ahead_capped: bool,
+    /// Authors of the commits behind, if requested
+    behind_authors: Vec<String>,
+    /// Whether listing authors stopped before reaching the merge base
+    behind_authors_capped: bool,
+    /// Whether the branch shares no commit with its base, if requested
+    unrelated: bool,
+    /// Whether the branch is a base branch or points at its base commit, so it is never deleted
+    at_base: bool,
+    /// Whether the tip is reachable from the base, if '--print-delete-commands' is given
+    merged: bool,
+    /// Why commits ahead and behind could not be counted
+    compare_error: Option<String>,
+}
+
+/// Reason why a branch is not part of the overview
+enum SkipReason {
+    /// The branch is excluded by the options, or has no comparison target
+    Filtered,
+    /// The branch tip can't be peeled to a commit
+    UnresolvableTip(String),
+    /// The local branch has no upstream to compare with
+    NoUpstream(String),
+}
+
+impl FormatedBranch {
+    fn from_branch(
+        repo: &Repository,
+        branch: &Branch,
+        opt: &Opt,
+        bases: &Bases,
+    ) -> Result<Self, SkipReason> {
+        use SkipReason::Filtered;
+
+        let lossy_name = branch.get().name().is_none();
+        let full_name = match branch.get().name() {
+            Some(full_name) => full_name.into(),
+            None if opt.utf8_safe => String::from_utf8_lossy(branch.get().name_bytes()),
+            None => return Err(Filtered),
+        };
+
+        // Keep the current branch regardless of filters, if needed
+        let is_head = branch.is_head();
+        if opt.exclude_current_branch && is_head {
+            return Err(Filtered);
+        }
+        let keep = opt.include_current_branch && is_head;
+
+        let (kind, name, remote): (RefKind, String, Option<String>) =
+            if full_name.starts_with("refs/remotes/") {
+                let mut parts = full_name.splitn(4, '/');
+                let remote_name = parts.nth(2).ok_or(Filtered)?.into();
+
+                // Only keep selected remotes, if needed
+                if !keep
+                    && ((!opt.remotes.is_empty() && !opt.remotes.contains(&remote_name))
+                        || opt.ignored_remotes.contains(&remote_name))
+                {
+                    return Err(Filtered);
+                }
+
+                (
+                    RefKind::Remote,
+                    parts.next().ok_or(Filtered)?.into(),
+                    Some(remote_name),
+                )
+            } else if let Some(name) = full_name.strip_prefix("refs/heads/") {
+                (RefKind::Local, name.into(), None)
+            } else {
+                return Err(Filtered);
+            };
+
+        if !keep
+            && !opt.patterns.is_empty()
+            && !opt
+                .patterns
+                .iter()
+                .any(|pattern| matches_pattern(pattern, &name))
+        {
+            return Err(Filtered);
+        }
+
+        let target = if opt.compare_with_upstream_branches {
+            let upstream = match branch.upstream() {
+                Ok(upstream) => upstream,
+                Err(_) if opt.needs_pull && remote.is_none() => {
+                    return Err(SkipReason::NoUpstream(name))
+                }
+                Err(_) => return Err(Filtered),
+            };
+
+            // Only keep branches tracking the selected remote, if needed
+            if let Some(upstream_remote) = opt.upstream_remote.as_ref().filter(|_| !keep) {
+                let prefix = format!("refs/remotes/{}/", upstream_remote);
+                if !upstream
+                    .get()
+                    .name()
+                    .is_some_and(|name| name.starts_with(&prefix))
+                {
+                    return Err(Filtered);
+                }
+            }
+
+            upstream.get().target().ok_or(Filtered)?
+        } else if opt.compare_with_local_branches {
+            // Local branches don't have a local counterpart
+            remote.as_ref().ok_or(Filtered)?;
+            repo.find_branch(&name, BranchType::Local)
+                .map_err(|_| Filtered)?
+                .get()
+                .target()
+                .ok_or(Filtered)?
+        } else {
+            bases.for_branch(&name)
+        };
+
+        let shorthand = match remote.as_ref() {
+            Some(remote) => format!("{}/{}", remote, name),
+            None => name.clone(),
+        };
+
+        let (last_commit_time, last_commit_offset, tip_summary, tip_author) =
+            match branch.get().peel_to_commit() {
+                Ok(commit) => (
+                    commit.author().when().seconds(),
+                    commit.author().when().offset_minutes(),
+                    commit.summary_bytes().map_or(String::new(), |summary| {
+                        String::from_utf8_lossy(summary).into_owned()
+                    }),
+                    String::from_utf8_lossy(commit.author().name_bytes()).into_owned(),
+                ),
+                Err(_) => return Err(SkipReason::UnresolvableTip(shorthand)),
+            };
+
+        let tip = branch.get().target().ok_or(Filtered)?;
+        // Branches without a merge base keep their base, to show them as they are
+        let target = if opt.since_divergence {
+            repo.merge_base(tip, target).unwrap_or(target)
+        } else {
+            target
+        };
+        let counts = match opt.count_cap {
+            // Only a revwalk can skip commits
+            cap if cap.is_some() || opt.merges() != Merges::Included => {
+                let cap = cap.unwrap_or(usize::MAX);
+                count_commits(repo, tip, target, cap, opt.merges()).and_then(|ahead| {
+                    Ok((ahead, count_commits(repo, target, tip, cap, opt.merges())?))
+                })
+            }
+            _ => repo
+                .graph_ahead_behind(tip, target)
+                .map(|(ahead, behind)| ((ahead, false), (behind, false))),
+        };
+
+        let describe_error = |error: git2::Error| {
+            if opt.verbose {
+                format!("{} (comparing {} with {})", error.message(), tip, target)
+            } else {
+                error.message().to_string()
+            }
+        };
+
+        // Keep branches that can't be compared (ex: with a missing object) to show the error
+        let (((ahead, ahead_capped), (behind, behind_capped)), mut compare_error) = match counts {
+            Ok(counts) => (counts, None),
+            Err(error) => (((0, false), (0, false)), Some(describe_error(error))),
+        };
+
+        let base = bases.for_branch(&name);
+        let at_base = tip == base || bases.branch_refs.iter().any(|base| *base == full_name);
+        // Counts may be against an upstream or a local branch, so check against the base itself
+        let merged = opt.print_delete_commands
+            && compare_error.is_none()
+            && repo.graph_descendant_of(base, tip).unwrap_or(false);
+
+        // Branches without a merge base are only detected when needed, to keep things fast
+        let unrelated = opt.unrelated_mode != UnrelatedMode::Show
+            && compare_error.is_none()
+            && match repo.merge_base(tip, target) {
+                Ok(_) => false,
+                Err(ref error) if error.code() == ErrorCode::NotFound => true,
+                Err(error) => {
+                    compare_error = Some(describe_error(error));
+                    false
+                }
+            };
+        if !keep && unrelated && opt.unrelated_mode == UnrelatedMode::Skip {
+            return Err(Filtered);
+        }
+
+        let note = if opt.columns().contains(&Column::Note) {
+            repo.find_note(opt.notes_ref.as_deref(), tip)
+                .ok()
+                .map(|note| {
+                    String::from_utf8_lossy(note.message_bytes())
+                        .lines()
+                        .next()
+                        .unwrap_or("")
+                        .to_string()
+                })
+        } else {
+            None
+        };
+
+        let signed = opt.columns().contains(&Column::Signature)
+            && repo.extract_signature(&tip, None).is_ok();
+
+        // Compare with the upstream independently of the main comparison
+        let upstream_divergence = if opt.columns().contains(&Column::Sync)
+            || opt.columns().contains(&Column::UpstreamAheadBehind)
+        {
+            branch
+                .upstream()
+                .ok()
+                .and_then(|upstream| upstream.get().target())
+                .and_then(|upstream| match repo.graph_ahead_behind(tip, upstream) {
+                    Ok(ahead_behind) => Some(ahead_behind),
+                    Err(error) => {
+                        if opt.verbose {
+                            eprintln!(
+                                "could not compare {} with its upstream: {} (comparing {} with {})",
+                                shorthand,
+                                error.message(),
+                                tip,
+                                upstream
+                            );
+                        }
+                        None
+                    }
+                })
+        } else {
+            None
+        };
+        let sync = upstream_divergence
+            .filter(|_| opt.columns().contains(&Column::Sync))
+            .map(|ahead_behind| match ahead_behind {
+                (0, 0) => SyncState::Synced,
+                (_, 0) => SyncState::Push,
+                (0, _) => SyncState::Pull,
+                _ => SyncState::Diverged,
+            });
+
+        let (behind_authors, behind_authors_capped) =
+            if opt.columns().contains(&Column::BehindAuthors) && behind > 0 {
+                commit_authors(repo, target, tip, opt.count_cap.unwrap_or(usize::MAX))
+                    .unwrap_or_default()
+            } else {
+                (Vec::new(), false)
+            };
+
+        let name = match opt
+            .strip_prefix
+            .as_ref()
+            .and_then(|prefix| name.strip_prefix(prefix.as_str()))
+        {
+            Some(stripped) => stripped.into(),
+            None => name,
+        };
+
+        Ok(Self {
+            kind,
+            last_commit_time,
+            last_commit_offset,
+            tip_summary,
+            tip_author,
+            note,
+            shorthand,
+            remotes: remote.iter().cloned().collect(),
+            remote,
+            name,
+            is_head,
+            lossy_name,
+            tip,
+            signed,
+            sync,
+            upstream_divergence,
+            behind,
+            behind_capped,
+            ahead,
+            ahead_capped,
+            behind_authors,
+            behind_authors_capped,
+            unrelated,
+            at_base,
+            merged,
+            compare_error,
+        })
+    }
+
+    /// Copy of the branch compared with its upstream instead of its base, to draw the upstream
+    /// chart
+    fn upstream_branch(&self) -> Option<Self> {
+        let (ahead, behind) = self.upstream_divergence?;
+        Some(Self {
+            ahead,
+            ahead_capped: false,
+            behind,
+            behind_capped: false,
+            unrelated: false,
+            compare_error: None,
+            ..self.clone()
+        })
+    }
+
+    /// Group of this branch, `None` being the group of local branches or of names without prefix
+    fn group(&self, group_by: GroupBy) -> Option<&str> {
+        match group_by {
+            GroupBy::Remote => self.remote.as_deref(),
+            GroupBy::Prefix => self.name.split_once('/').map(|(prefix, _)| prefix),
+        }
+    }
+
+    /// Name to show, with the remote if requested
+    fn display_name(&self, opt: &Opt) -> String {
+        match self.remote.as_ref().filter(|_| opt.keep_remote_prefix) {
+            Some(remote) => format!("{}/{}", remote, self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Name shown in tables, marked if it is the current branch and fitted to '--max-name-width'
+    fn name_label(&self, opt: &Opt, glyphs: &Glyphs) -> String {
+        let name = if opt.include_current_branch && self.is_head {
+            format!("* {}", self.display_name(opt))
+        } else {
+            self.display_name(opt)
+        };
+        match opt.max_name_width {
+            Some(width) => fit_width(&name, width, glyphs.ellipsis),
+            None => name,
+        }
+    }
+
+    /// Whether the branch is merged and not a base branch, for '--print-delete-commands'
+    fn is_deletable(&self, opt: &Opt) -> bool {
+        (self.merged || opt.merged_into.is_some())
+            && !self.lossy_name
+            && self.compare_error.is_none()
+            && !self.at_base
+            && !self.is_head
+    }
+
+    /// Git commands to delete this branch
+    fn delete_commands(&self) -> Vec<String> {
+        match self.remote.as_ref() {
+            None => vec![format!("git branch -d {}", shell_quote(&self.shorthand))],
+            // The branch name, without the remote prefix.  Deduplicated branches have the same
+            // name on all their remotes.
+            Some(_) => {
+                let name = self.shorthand.split_once('/').map_or("", |(_, name)| name);
+                self.remotes
+                    .iter()
+                    .map(|remote| {
+                        format!(
+                            "git push {} --delete {}",
+                            shell_quote(remote),
+                            shell_quote(name)
+                        )
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Remotes of the branch, or 'local'
+    fn remote_label(&self) -> String {
+        match self.remote {
+            Some(_) => self.remotes.join(", "),
+            None => "local".into(),
+        }
+    }
+
+    fn oid_label(&self, opt: &Opt) -> String {
+        let oid = self.tip.to_string();
+        if opt.oid_full {
+            oid
+        } else {
+            oid[..opt.oid_length.unwrap_or(DEFAULT_OID_LENGTH)].into()
+        }
+    }
+
+    fn signature_label(&self) -> &'static str {
+        if self.signed {
+            "signed"
+        } else {
+            "unsigned"
+        }
+    }
+
+    /// Value of a field used in expressions
+    fn field(&self, field: Field, now: i64) -> f64 {
+        match field {
+            Field::Ahead => self.ahead as f64,
+            Field::Behind => self.behind as f64,
+            Field::AgeDays => ((now - self.last_commit_time) / DAY) as f64,
+        }
+    }
+
+    fn percent_label(&self, glyphs: &Glyphs) -> String {
+        match self.ahead + self.behind {
+            0 => glyphs.missing.into(),
+            total => format!("{}%", (self.ahead * 100 + total / 2) / total),
+        }
+    }
+
+    fn behind_authors_label(&self) -> String {
+        // Capped lists may miss authors of older commits
+        let capped_mark = if self.behind_authors_capped { "+" } else { "" };
+        if self.behind_authors.len() > MAX_BEHIND_AUTHORS {
+            format!(
+                "by: {} and {}{} more",
+                self.behind_authors[..MAX_BEHIND_AUTHORS].join(", "),
+                self.behind_authors.len() - MAX_BEHIND_AUTHORS,
+                capped_mark
+            )
+        } else if self.behind_authors.is_empty() {
+            String::new()
+        } else if self.behind_authors_capped {
+            format!("by: {} and more", self.behind_authors.join(", "))
+        } else {
+            format!("by: {}", self.behind_authors.join(", "))
+        }
+    }
+
+    fn sync_label(&self) -> &'static str {
+        self.sync.map_or("", SyncState::name)
+    }
+
+    fn ahead_label(&self) -> String {
+        count_label(self.ahead, self.ahead_capped)
+    }
+
+    fn behind_label(&self) -> String {
+        count_label(self.behind, self.behind_capped)
+    }
+
+    fn format_tracking_sign(&self, glyphs: &Glyphs) -> String {
+        match (self.ahead, self.behind) {
+            (0, 0) => glyphs.synced_sign.into(),
+            (_, 0) => format!("{}{}", glyphs.ahead_sign, self.ahead_label()),
+            (0, _) => format!("{}{}", glyphs.behind_sign, self.behind_label()),
+            (_, _) => format!(
+                "{}{} {}{}",
+                glyphs.ahead_sign,
+                self.ahead_label(),
+                glyphs.behind_sign,
+                self.behind_label()
+            ),
+        }
+    }
+
+    /// Draw the chart, with counts aligned on `label_width` characters and bars up to `bar_width`
+    /// characters
+    fn format_chart_line(
+        &self,
+        max: usize,
+        label_width: usize,
+        bar_width: usize,
+        style: ChartStyle,
+        reverse: bool,
+        glyphs: &Glyphs,
+    ) -> String {
+        let label = if self.compare_error.is_some() {
+            Some("error")
+        } else if self.unrelated {
+            Some("unrelated")
+        } else {
+            None
+        };
+        if let Some(label) = label {
+            let width = 2 * bar_width + 2 * label_width + 3;
+            let left = width.saturating_sub(label.width()) / 2;
+            return fit_width(&format!("{}{}", " ".repeat(left), label), width, "");
+        }
+        match style {
+            ChartStyle::Split => {
+                self.format_split_chart_line(max, label_width, bar_width, reverse, glyphs)
+            }
+            ChartStyle::Stacked => {
+                self.format_stacked_chart_line(max, label_width, bar_width, reverse, glyphs)
+            }
+            ChartStyle::Ratio => {
+                self.format_ratio_chart_line(label_width, bar_width, reverse, glyphs)
+            }
+        }
+    }
+
+    /// Counts and labels drawn on the left and right of the chart, commits behind being on the
+    /// left unless reversed.  Reversed non-zero labels are signed to tell them apart.
+    fn chart_sides(&self, reverse: bool) -> ((usize, String), (usize, String)) {
+        let signed = |sign, count, label: String| {
+            if count == 0 {
+                label
+            } else {
+                format!("{}{}", sign, label)
+            }
+        };
+        if reverse {
+            (
+                (self.ahead, signed('+', self.ahead, self.ahead_label())),
+                (self.behind, signed('-', self.behind, self.behind_label())),
+            )
+        } else {
+            (
+                (self.behind, self.behind_label()),
+                (self.ahead, self.ahead_label()),
+            )
+        }
+    }
+
+    /// Draw commits behind on the left and commits ahead on the right of a middle bar, or the
+    /// opposite if reversed
+    fn format_split_chart_line(
+        &self,
+        max: usize,
+        label_width: usize,
+        bar_width: usize,
+        reverse: bool,
+        glyphs: &Glyphs,
+    ) -> String {
+        let ((left, left_label), (right, right_label)) = self.chart_sides(reverse);
+        let mut result = String::new();
+
+        // First half
+        {
+            let (left_size, left_half) = branch_size(left, max, bar_width);
+
+            result
+                .extend(repeat(' ').take(bar_width + label_width - left_label.width() - left_size));
+
+            write!(result, "{} ", left_label).unwrap();
+
+            if left_half {
+                result.push(glyphs.behind_half_bar);
+                result.extend(repeat(glyphs.bar).take(left_size - 1));
+            } else {
+                result.extend(repeat(glyphs.bar).take(left_size));
+            }
+        }
+
+        // Middle bar
+        result.push(if left == 0 && right == 0 {
+            glyphs.middle
+        } else if left == 0 {
+            glyphs.middle_ahead
+        } else if right == 0 {
+            glyphs.middle_behind
+        } else {
+            glyphs.middle_diverged
+        });
+
+        // Second half
+        {
+            let (right_size, right_half) = branch_size(right, max, bar_width);
+
+            if right_half {
+                result.extend(repeat(glyphs.bar).take(right_size - 1));
+                result.push(glyphs.ahead_half_bar);
+            } else {
+                result.extend(repeat(glyphs.bar).take(right_size));
+            }
+
+            write!(result, " {}", right_label).unwrap();
+
+            result.extend(
+                repeat(' ').take(label_width - right_label.width() + bar_width - right_size),
+            );
+        }
+
+        result
+    }
+
+    /// Draw commits behind followed by commits ahead in a single bar, or the opposite if reversed
+    fn format_stacked_chart_line(
+        &self,
+        max: usize,
+        label_width: usize,
+        bar_width: usize,
+        reverse: bool,
+        glyphs: &Glyphs,
+    ) -> String {
+        let ((left, left_label), (right, right_label)) = self.chart_sides(reverse);
+        let (left_bar, right_bar) = if reverse {
+            (glyphs.stacked_ahead_bar, glyphs.stacked_behind_bar)
+        } else {
+            (glyphs.stacked_behind_bar, glyphs.stacked_ahead_bar)
+        };
+        let (left_size, _) = branch_size(left, max, bar_width);
+        let (right_size, _) = branch_size(right, max, bar_width);
+
+        let mut result = String::new();
+        result.extend(repeat(' ').take(label_width - left_label.width()));
+        write!(result, "{} ", left_label).unwrap();
+        result.extend(repeat(left_bar).take(left_size));
+        result.extend(repeat(right_bar).take(right_size));
+        write!(result, " {}", right_label).unwrap();
+
+        // Pad to the width of the split chart
+        result.extend(
+            repeat(' ').take(
+                label_width - right_label.width() + 2 * bar_width + 1 - left_size - right_size,
+            ),
+        );
+        result
+    }
+
+    /// Share commits behind and ahead of a bar as wide as the split chart, ignoring how many they
+    /// are.  Non-zero counts always get at least one character.
+    fn format_ratio_chart_line(
+        &self,
+        label_width: usize,
+        bar_width: usize,
+        reverse: bool,
+        glyphs: &Glyphs,
+    ) -> String {
+        let ((left, left_label), (right, right_label)) = self.chart_sides(reverse);
+        let (left_bar, right_bar) = if reverse {
+            (glyphs.stacked_ahead_bar, glyphs.stacked_behind_bar)
+        } else {
+            (glyphs.stacked_behind_bar, glyphs.stacked_ahead_bar)
+        };
+        let (left_color, right_color, default_color) = match glyphs.ratio_colors {
+            Some((behind, ahead)) if reverse => (ahead, behind, DEFAULT_COLOR),
+            Some((behind, ahead)) => (behind, ahead, DEFAULT_COLOR),
+            None => ("", "", ""),
+        };
+        let width = 2 * bar_width + 1;
+
+        let mut result = String::new();
+        result.extend(repeat(' ').take(label_width - left_label.width()));
+        write!(result, "{} ", left_label).unwrap();
+        match left + right {
+            0 => {
+                result.extend(repeat(' ').take(bar_width));
+                result.push(glyphs.middle);
+                result.extend(repeat(' ').take(bar_width));
+            }
+            total => {
+                let left_size = ((left * width + total / 2) / total)
+                    .max(usize::from(left > 0))
+                    .min(width - usize::from(right > 0));
+                result.push_str(left_color);
+                result.extend(repeat(left_bar).take(left_size));
+                result.push_str(right_color);
+                result.extend(repeat(right_bar).take(width - left_size));
+                result.push_str(default_color);
+            }
+        }
+        write!(result, " {}", right_label).unwrap();
+        result.extend(repeat(' ').take(label_width - right_label.width()));
+        result
+    }
+}
+
+fn compare_branches(a: &FormatedBranch, b: &FormatedBranch) -> std::cmp::Ordering {
+    // Compare commit authoring date
+    b.last_commit_time
+        .cmp(&a.last_commit_time)
+        // Compare remotes
+        .then_with(|| match (a.remote.as_ref(), b.remote.as_ref()) {
+            (Some(remote_a), Some(remote_b)) => remote_a.cmp(remote_b),
+            (None, Some(_)) => std::cmp::Ordering::Less,
+            (Some(_), None) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        })
+        // Compare names
+        .then_with(|| a.name.cmp(&b.name))
+}
+
+/// Key ordering groups, remotes listed with '--remote-sort' coming first in the given order
+fn group_key<'a>(
+    opt: &Opt,
+    branch: &'a FormatedBranch,
+    group_by: GroupBy,
+) -> Option<(usize, &'a str)> {
+    branch.group(group_by).map(|group| {
+        let rank = match group_by {
+            GroupBy::Remote => opt
+                .remote_order
+                .iter()
+                .position(|remote| remote == group)
+                .unwrap_or(opt.remote_order.len()),
+            GroupBy::Prefix => 0,
+        };
+        (rank, group)
+    })
+}
+
+/// Branches sharing their tip with branches of another name, the same name on several remotes not
+/// being a duplicate
+fn duplicate_branches(branches: &[FormatedBranch]) -> Vec<(Oid, Vec<String>)> {
+    let mut groups: Vec<(Oid, Vec<&FormatedBranch>)> = Vec::new();
+    for branch in branches {
+        match groups.iter_mut().find(|(tip, _)| *tip == branch.tip) {
+            Some((_, group)) => group.push(branch),
+            None => groups.push((branch.tip, vec![branch])),
+        }
+    }
+    groups
+        .into_iter()
+        .filter(|(_, group)| group.iter().any(|branch| branch.name != group[0].name))
+        .map(|(tip, group)| {
+            (
+                tip,
+                group
+                    .iter()
+                    .map(|branch| branch.shorthand.clone())
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+fn dedupe_branches(branches: Vec<FormatedBranch>) -> Vec<FormatedBranch> {
+    let mut result: Vec<FormatedBranch> = Vec::new();
+    let mut indexes: HashMap<(String, Oid), usize> = HashMap::new();
+
+    for branch in branches {
+        let remote = match branch.remote.as_ref() {
+            Some(remote) => remote,
+            None => {
+                result.push(branch);
+                continue;
+            }
+        };
+
+        match indexes.entry((branch.name.clone(), branch.tip)) {
+            Entry::Occupied(entry) => result[*entry.get()].remotes.push(remote.clone()),
+            Entry::Vacant(entry) => {
+                entry.insert(result.len());
+                result.push(branch);
+            }
+        }
+    }
+
+    result
+}
+
+#[derive(Debug)]
+enum CliError {
+    GitError(git2::Error),
+    IoError(std::io::Error),
+    UnknownRevision(String, Vec<String>),
+    UnknownTag(String),
+    HistoryTooShort(usize, usize),
+    ReflogTooShort(String, usize, usize),
+    AmbiguousRevision(String),
+    NoMergeBase(String, String),
+    InvalidTemplate(PathBuf, String),
+    NoBranches,
+    StaleBranches(u64, Vec<String>),
+}
+
+impl From<git2::Error> for CliError {
+    fn from(error: git2::Error) -> Self {
+        CliError::GitError(error)
+    }
+}
+
+impl From<std::io::Error> for CliError {
+    fn from(error: std::io::Error) -> Self {
+        CliError::IoError(error)
+    }
+}
+
+fn run() -> Result<(), CliError> {
+    let matches = Opt::clap().get_matches();
+    let mut opt = Opt::from_clap(&matches);
+
+    if let Some(shell) = opt.generate_completion {
+        Opt::clap().gen_completions_to(env!("CARGO_PKG_NAME"), shell, &mut std::io::stdout());
+        return Ok(());
+    }
+
+    if opt.list_branch_names {
+        return print_branch_names(&opt);
+    }
+
+    if !opt.remotes.is_empty() || opt.compare_with_local_branches {
+        opt.remote_branches = true;
+    }
+
+    if opt.upstream_remote.is_some() || opt.needs_push || opt.needs_pull {
+        opt.compare_with_upstream_branches = true;
+    }
+
+    if !console::init() {
+        opt.ascii = true;
+    }
+
+    if opt.config_dump {
+        print!("{}", output::config_json(&opt));
+        return Ok(());
+    }
+
+    if opt.stdin_base {
+        opt.base_revision = read_base_revision(std::io::stdin().lock())?;
+    }
+
+    if opt.autodetect_base
+        && matches.occurrences_of("base_revision") == 0
+        && opt.since_tag.is_none()
+        && opt.relative_base.is_none()
+        && opt.merge_base_revisions.is_empty()
+    {
+        opt.base_revision = detect_base(&open_repository(&opt)?);
+        eprintln!("Using '{}' as base", opt.base_revision);
+    }
+
+    let template = match opt.template_file.as_ref() {
+        Some(path) => Some(read_template(path)?),
+        None => None,
+    };
+    if let Some(template) = template.as_ref() {
+        // Only compute what the template shows
+        opt.columns = template.columns();
+    }
+
+    if opt.oid_length.is_none() && (opt.columns().contains(&Column::Oid) || opt.detect_duplicates) {
+        opt.oid_length = Some(abbrev_length(&open_repository(&opt)?));
+    }
+
+    // Read names before watching, as the standard input can only be read once
+    let selected_branches = if opt.branches_from_stdin {
+        Some(read_branch_names(std::io::stdin().lock())?)
+    } else {
+        None
+    };
+
+    if opt.explain {
+        explain(&opt);
+    }
+
+    // '--watch' may be given without a value, so check its presence directly
+    if matches.is_present("watch") {
+        let interval = Duration::from_secs(opt.watch.unwrap_or(5));
+        loop {
+            // Clear the screen and move the cursor to the top left corner, if it is a terminal
+            if console::width().is_some() {
+                print!("\x1b[2J\x1b[H");
+            }
+            print_overview(&opt, selected_branches.as_deref(), template.as_ref())?;
+            thread::sleep(interval);
+        }
+    }
+
+    print_overview(&opt, selected_branches.as_deref(), template.as_ref())
+}
+
+fn read_template(path: &Path) -> Result<output::Template, CliError> {
+    let source = fs::read_to_string(path)
+        .map_err(|error| io::Error::new(error.kind(), format!("{}: {}", path.display(), error)))?;
+    source
+        .parse()
+        .map_err(|error| CliError::InvalidTemplate(path.into(), error))
+}
+
+fn open_repository(opt: &Opt) -> Result<Repository, git2::Error> {
+    match opt.repo_path.as_ref() {
+        Some(repo_path) => Repository::open(repo_path),
+        None => Repository::open_from_env(),
+    }
+}
+
+/// Guess the integration branch to use as a base
+fn detect_base(repo: &Repository) -> String {
+    let upstream = repo
+        .head()
+        .ok()
+        .filter(|head| head.is_branch())
+        .and_then(|head| {
+            let branch = Branch::wrap(head);
+            let upstream = branch.upstream().ok()?;
+            let name = upstream.name().ok()??.to_string();
+            Some(name)
+        });
+    upstream
+        .into_iter()
+        .chain(
+            ["main", "master", "develop"]
+                .iter()
+                .map(|name| name.to_string()),
+        )
+        .find(|revision| repo.revparse_single(revision).is_ok())
+        .unwrap_or_else(|| "HEAD".into())
+}
+
+/// Abbreviated hash length configured by 'core.abbrev', like git does
+fn abbrev_length(repo: &Repository) -> usize {
+    match repo
+        .config()
+        .and_then(|config| config.get_string("core.abbrev"))
+    {
+        Ok(ref value) if value == "no" => 40,
+        Ok(value) => value
+            .parse()
+            .map_or(DEFAULT_OID_LENGTH, |length: usize| length.clamp(4, 40)),
+        Err(_) => DEFAULT_OID_LENGTH,
+    }
+}
+
+/// Whether `base` is HEAD and tracked files have changes
+fn has_uncommitted_changes(repo: &Repository, base: Oid) -> bool {
+    let head = repo.head().ok().and_then(|head| head.target());
+    let mut options = StatusOptions::new();
+    options.include_untracked(false);
+    head == Some(base)
+        && repo
+            .statuses(Some(&mut options))
+            .is_ok_and(|statuses| !statuses.is_empty())
+}
+
+fn print_branch_names(opt: &Opt) -> Result<(), CliError> {
+    let repo = open_repository(opt)?;
+    let mut names: Vec<String> = repo
+        .branches(None)?
+        .filter_map(|result| result.ok()?.0.name().ok()?.map(String::from))
+        .collect();
+    names.sort();
+    for name in names {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+/// Read branch names, one per line, ignoring markers of 'git branch' output, like the 'remotes/'
+/// prefix of 'git branch -a' and symbolic references ('origin/HEAD -> origin/main')
+fn read_branch_names(input: impl BufRead) -> Result<Vec<String>, CliError> {
+    let mut names = Vec::new();
+    for line in input.lines() {
+        let line = line?;
+        let name = line.trim_start_matches(['*', '+']).trim();
+        let name = name.strip_prefix("remotes/").unwrap_or(name);
+        if !name.is_empty() && !name.contains(" -> ") {
+            names.push(name.to_string());
+        }
+    }
+    Ok(names)
+}
+
+fn read_base_revision(mut input: impl BufRead) -> Result<String, CliError> {
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    match line.trim() {
+        "" => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "no base revision on the standard input",
+        )
+        .into()),
+        revision => Ok(revision.into()),
+    }
+}
+
+/// Print the overview of `selected_branches` if given, or of branches of the selected kinds
+fn print_overview(
+    opt: &Opt,
+    selected_branches: Option<&[String]>,
+    template: Option<&output::Template>,
+) -> Result<(), CliError> {
+    let repo = open_repository(opt)?;
+    let bases = Bases::resolve(&repo, opt)?;
+
+    if opt.check_worktree
+        && !opt.compare_with_upstream_branches
+        && !opt.compare_with_local_branches
+        && has_uncommitted_changes(&repo, bases.default)
+    {
+        eprintln!("Note: the worktree has uncommitted changes, only committed state is shown");
+    }
+
+    let listed_branches: Vec<Branch> = match selected_branches {
+        Some(names) => names
+            .iter()
+            .filter_map(|name| {
+                match repo
+                    .find_branch(name, BranchType::Local)
+                    .or_else(|_| repo.find_branch(name, BranchType::Remote))
+                {
+                    Ok(branch) => Some(branch),
+                    Err(_) => {
+                        eprintln!("skipped unknown branch '{}'", name);
+                        None
+                    }
+                }
+            })
+            .collect(),
+        None => {
+            let branch_type = if opt.all_branches || (opt.remote_branches && opt.local_branches) {
+                None
+            } else if opt.remote_branches {
+                Some(BranchType::Remote)
+            } else {
+                Some(BranchType::Local)
+            };
+            let mut branches: Vec<Branch> = repo
+                .branches(branch_type)?
+                .filter_map(|result| Some(result.ok()?.0))
+                .collect();
+            // The current branch is local, so list it with remote branches too, if needed
+            if opt.include_current_branch && branch_type == Some(BranchType::Remote) {
+                branches.extend(
+                    repo.head()
+                        .ok()
+                        .filter(|head| head.is_branch())
+                        .map(Branch::wrap),
+                );
+            }
+            branches
+        }
+    };
+
+    let mut unresolvable_tips = Vec::new();
+    let mut without_upstream = Vec::new();
+    let mut branches: Vec<_> = listed_branches
+        .iter()
+        .filter_map(
+            |branch| match FormatedBranch::from_branch(&repo, branch, opt, &bases) {
+                Ok(branch) => Some(branch),
+                Err(SkipReason::UnresolvableTip(name)) => {
+                    unresolvable_tips.push(name);
+                    None
+                }
+                Err(SkipReason::NoUpstream(name)) => {
+                    without_upstream.push(name);
+                    None
+                }
+                Err(SkipReason::Filtered) => None,
+            },
+        )
+        .collect();
+
+    if !unresolvable_tips.is_empty() {
+        eprintln!(
+            "skipped {} branch{} with unresolvable tip: {}",
+            unresolvable_tips.len(),
+            if unresolvable_tips.len() == 1 {
+                ""
+            } else {
+                "es"
+            },
+            unresolvable_tips.join(", ")
+        );
+    }
+
+    if !without_upstream.is_empty() {
+        eprintln!(
+            "{} branch{} without upstream: {}",
+            without_upstream.len(),
+            if without_upstream.len() == 1 {
+                ""
+            } else {
+                "es"
+            },
+            without_upstream.join(", ")
+        );
+    }
+
+    for branch in branches.iter() {
+        if let Some(error) = branch.compare_error.as_ref() {
+            eprintln!("could not compare {}: {}", branch.shorthand, error);
+        }
+    }
+
+    if let Some(merged_into) = opt.merged_into.as_ref() {
+        let merged_into = resolve_revision(&repo, merged_into)?;
+        branches.retain(|branch| {
+            (opt.include_current_branch && branch.is_head)
+                || branch.tip == merged_into
+                || repo
+                    .graph_descendant_of(merged_into, branch.tip)
+                    .unwrap_or(false)
+        });
+    }
+
+    if opt.needs_push {
+        branches
+            .retain(|branch| (opt.include_current_branch && branch.is_head) || branch.ahead > 0);
+    }
+
+    if opt.needs_pull {
+        branches
+            .retain(|branch| (opt.include_current_branch && branch.is_head) || branch.behind > 0);
+    }
+
+    if let Some(filter_expr) = opt.filter_expr.as_ref() {
+        let now = now();
+        branches.retain(|branch| {
+            (opt.include_current_branch && branch.is_head)
+                || filter_expr.evaluate(&|field| branch.field(field, now)) != 0.
+        });
+    }
+
+    if opt.dedupe {
+        branches = dedupe_branches(branches);
+    }
+
+    // Durations too long to be represented can't be exceeded
+    let stale_age = opt
+        .fail_on_stale
+        .and_then(|days| i64::try_from(days).ok()?.checked_mul(DAY));
+    let stale_branches: Vec<_> = match stale_age {
+        Some(age) => {
+            let now = now();
+            branches
+                .iter()
+                .filter(|branch| now - branch.last_commit_time > age)
+                .map(|branch| branch.shorthand.clone())
+                .collect()
+        }
+        None => Vec::new(),
+    };
+
+    let duplicates = if opt.detect_duplicates {
+        duplicate_branches(&branches)
+    } else {
+        Vec::new()
+    };
+
+    print_branches(opt, branches, template)?;
+
+    for (tip, names) in duplicates {
+        eprintln!(
+            "branches {} point at the same commit {}",
+            names.join(", "),
+            &tip.to_string()[..opt.oid_length.unwrap_or(DEFAULT_OID_LENGTH)]
+        );
+    }
+
+    match opt.fail_on_stale {
+        Some(days) if !stale_branches.is_empty() => {
+            Err(CliError::StaleBranches(days, stale_branches))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn print_branches(
+    opt: &Opt,
+    mut branches: Vec<FormatedBranch>,
+    template: Option<&output::Template>,
+) -> Result<(), CliError> {
+    if opt.fail_on_empty && branches.is_empty() {
+        return Err(CliError::NoBranches);
+    }
+
+    if opt.count {
+        println!("{}", branches.len());
+        return Ok(());
+    }
+
+    if opt.summary_json {
+        print!("{}", output::summary_json(&branches));
+        return Ok(());
+    }
+
+    if branches.is_empty() {
+        if !opt.empty_ok {
+            eprintln!("No branches to show");
+        }
+        return Ok(());
+    }
+
+    if !opt.no_sort {
+        branches.sort_by(compare_branches);
+    }
+
+    if let Some(sort_expr) = opt.sort_expr.as_ref() {
+        // The sort is stable, so branches with the same value keep the default order
+        let now = now();
+        branches.sort_by(|a, b| {
+            let value =
+                |branch: &FormatedBranch| sort_expr.evaluate(&|field| branch.field(field, now));
+            value(b).total_cmp(&value(a))
+        });
+    }
+
+    if let Some(group_by) = opt.group_by {
+        // The sort is stable, so branches stay sorted within their group
+        branches.sort_by(|a, b| group_key(opt, a, group_by).cmp(&group_key(opt, b, group_by)));
+    }
+
+    if opt.print_delete_commands {
+        // Commands can't refer to names with replaced characters
+        for branch in branches.iter().filter(|branch| branch.is_deletable(opt)) {
+            for command in branch.delete_commands() {
+                println!("{}", command);
+            }
+        }
+        return Ok(());
+    }
+
+    if opt.numeric_only || opt.null_terminated {
+        // Names with replaced characters would make scripts fail
+        let lossy_names: Vec<_> = branches
+            .iter()
+            .filter(|branch| branch.lossy_name)
+            .map(|branch| branch.shorthand.as_str())
+            .collect();
+        if !lossy_names.is_empty() {
+            eprintln!(
+                "skipped {} branch{} with invalid UTF-8 names: {}",
+                lossy_names.len(),
+                if lossy_names.len() == 1 { "" } else { "es" },
+                lossy_names.join(", ")
+            );
+        }
+    }
+
+    if opt.numeric_only {
+        for branch in branches.iter().filter(|branch| !branch.lossy_name) {
+            println!(
+                "{}\t{}\t{}",
+                branch.shorthand,
+                output::behind_value(branch),
+                output::ahead_value(branch)
+            );
+        }
+        return Ok(());
+    }
+
+    if opt.null_terminated {
+        for branch in branches.iter().filter(|branch| !branch.lossy_name) {
+            print!("{}\0", branch.shorthand);
+        }
+        return Ok(());
+    }
+
+    let mut synced_count = 0;
+    if opt.collapse_synced && opt.format == OutputFormat::Table {
+        let count = branches.len();
+        branches.retain(|branch| {
+            (opt.include_current_branch && branch.is_head)
+                || branch.ahead != 0
+                || branch.behind != 0
+                || branch.unrelated
+                || branch.compare_error.is_some()
+        });
+        synced_count = count - branches.len();
+    }
+
+    if let Some(template) = template {
+        print!("{}", template.render(opt, &branches));
+        return Ok(());
+    }
+
+    match opt.format {
+        OutputFormat::Table if branches.is_empty() => print_synced_count(opt, synced_count, true),
+        OutputFormat::Table if opt.compact => {
+            print_compact(opt, &branches);
+            print_synced_count(opt, synced_count, false);
+        }
+        OutputFormat::Table => print_table(opt, &branches, synced_count),
+        OutputFormat::Json => print!("{}", output::json(&branches)),
+        OutputFormat::Csv => print!("{}", output::csv(opt, &branches)),
+        OutputFormat::Tsv => print!("{}", output::tsv(opt, &branches)),
+        OutputFormat::Markdown => print!("{}", output::markdown(opt, &branches)),
+        OutputFormat::Html => print!("{}", output::html(opt, &branches)),
+    }
+
+    Ok(())
+}
+
+/// Mention branches left out by '--collapse-synced', if any
+fn print_synced_count(opt: &Opt, synced_count: usize, only_synced: bool) {
+    if synced_count > 0 {
+        println!(
+            "{}{} branch{} in sync with the {}",
+            if only_synced { "" } else { "and " },
+            synced_count,
+            if synced_count == 1 { "" } else { "es" },
+            base_label(opt)
+        );
+    }
+}
+
+/// Display width of the longest name, wide characters taking two columns
+fn names_width(opt: &Opt, branches: &[FormatedBranch]) -> usize {
+    branches
+        .iter()
+        .map(|branch| branch.display_name(opt).width())
+        .max()
+        .unwrap_or(0)
+}
+
+fn print_compact(opt: &Opt, branches: &[FormatedBranch]) {
+    let glyphs = if opt.ascii {
+        &ASCII_GLYPHS
+    } else {
+        &UNICODE_GLYPHS
+    };
+    let now = now();
+
+    // Like in tables, names of remote branches don't include their remote
+    let show_remote = opt.columns().contains(&Column::Remote);
+    let remote_width = branches
+        .iter()
+        .map(|branch| branch.remote_label().width())
+        .max()
+        .unwrap_or(0);
+    let name_width = branches
+        .iter()
+        .map(|branch| branch.name_label(opt, glyphs).width())
+        .max()
+        .unwrap_or(0);
+    let behind_width = branches
+        .iter()
+        .map(|branch| branch.behind_label().width())
+        .max()
+        .unwrap_or(0);
+    let ahead_width = branches
+        .iter()
+        .map(|branch| branch.ahead_label().width())
+        .max()
+        .unwrap_or(0);
+
+    for branch in branches {
+        if show_remote {
+            print!("{}  ", fit_width(&branch.remote_label(), remote_width, ""));
+        }
+        let behind_label = branch.behind_label();
+        println!(
+            "{}  -{}{} {} +{}  ({})",
+            fit_width(&branch.name_label(opt, glyphs), name_width, ""),
+            " ".repeat(behind_width - behind_label.width()),
+            behind_label,
+            glyphs.middle,
+            fit_width(&branch.ahead_label(), ahead_width, ""),
+            format_short_age(now - branch.last_commit_time),
+        );
+    }
+}
+
+fn print_table(opt: &Opt, branches: &[FormatedBranch], synced_count: usize) {
+    let glyphs = if opt.ascii {
+        &ASCII_GLYPHS
+    } else {
+        &UNICODE_GLYPHS
+    };
+    // Like other table colors, only color ratio charts in a terminal, and not in consoles falling
+    // back to ASCII as they may not interpret escape sequences
+    let colored_glyphs;
+    let glyphs = if opt.chart_style == ChartStyle::Ratio && !opt.ascii && console::width().is_some()
+    {
+        colored_glyphs = Glyphs {
+            ratio_colors: Some(RATIO_COLORS),
+            ..*glyphs
+        };
+        &colored_glyphs
+    } else {
+        glyphs
+    };
+
+    let mut table = Table::new();
+    let mut format = match opt.table_style {
+        TableStyle::Minimal => TableFormat::new(),
+        TableStyle::Bordered => *consts::FORMAT_NO_LINESEP,
+        TableStyle::Clean => *consts::FORMAT_CLEAN,
+    };
+    format.padding(opt.padding, opt.padding);
+    match (opt.table_style, opt.separator) {
+        (TableStyle::Clean, _) => {}
+        (_, Some(separator)) => format.column_separator(separator),
+        (TableStyle::Minimal, None) => format.column_separator(glyphs.column_separator),
+        (TableStyle::Bordered, None) => {}
+    }
+    table.set_format(format);
+
+    let (max, label_width) = chart_scale(opt, branches);
+    let upstream_branches: Vec<_> = branches
+        .iter()
+        .filter_map(FormatedBranch::upstream_branch)
+        .collect();
+    let (upstream_max, upstream_label_width) = chart_scale(opt, &upstream_branches);
+    let chart_label_width = |column: &Column| match column {
+        Column::UpstreamAheadBehind => upstream_label_width,
+        _ => label_width,
+    };
+
+    let now = now();
+    let mut columns = opt.columns();
+
+    if opt.columns.is_empty()
+        && branches
+            .iter()
+            .all(|branch| branch.kind == branches[0].kind)
+    {
+        columns.retain(|column| *column != Column::RefType);
+    }
+
+    if columns.contains(&Column::Remote) {
+        let first_remote = branches[0].remote.as_ref();
+        let single_remote = first_remote.filter(|_| {
+            branches
+                .iter()
+                .all(|branch| branch.remotes == branches[0].remotes)
+                && branches[0].remotes.len() == 1
+        });
+
+        // Don't repeat the remote on every row if it is the same for all branches, unless the
+        // columns were explicitly selected
+        if opt.hide_remote_label || (opt.columns.is_empty() && single_remote.is_some()) {
+            columns.retain(|column| *column != Column::Remote);
+            if let Some(remote) = single_remote {
+                println!("Remote: {}", remote);
+            }
+        }
+    }
+
+    let cell = |branch: &FormatedBranch, column: &Column, bar_width: usize| match column {
+        Column::RefType => Cell::new(branch.kind.name()).style_spec(branch.kind.style_spec()),
+        Column::Remote => {
+            Cell::new(&branch.remote_label()).style_spec(if branch.remote.is_none() {
+                "Fgb"
+            } else {
+                "Frb"
+            })
+        }
+        Column::Name => {
+            let is_current = opt.include_current_branch && branch.is_head;
+            let name = branch.name_label(opt, glyphs);
+            let color = match opt.color_by {
+                Some(ColorBy::Author) => key_style_spec(&branch.tip_author),
+                None => "",
+            };
+            let style = match (is_current, branch.lossy_name) {
+                (true, true) => "bi",
+                (true, false) => "b",
+                (false, true) => "i",
+                (false, false) => "",
+            };
+            Cell::new(&name).style_spec(&format!("{}{}", style, color))
+        }
+        Column::Date => Cell::new(&format_time(
+            branch.last_commit_time,
+            branch.last_commit_offset,
+            &opt.time_format,
+        )),
+        Column::Age => {
+            let age = now - branch.last_commit_time;
+            Cell::new(&format_age(age)).style_spec(age_style_spec(age))
+        }
+        Column::TrackingSign => Cell::new(&branch.format_tracking_sign(glyphs)),
+        Column::Oid => Cell::new(&branch.oid_label(opt)),
+        Column::Subject if branch.tip_summary.width() > SUBJECT_WIDTH => Cell::new(&fit_width(
+            &branch.tip_summary,
+            SUBJECT_WIDTH,
+            glyphs.ellipsis,
+        )),
+        Column::Subject => Cell::new(&branch.tip_summary),
+        Column::Note => Cell::new(branch.note.as_deref().unwrap_or("")),
+        Column::Signature => {
+            Cell::new(branch.signature_label()).style_spec(if branch.signed { "Fg" } else { "" })
+        }
+        Column::Sync => {
+            Cell::new(branch.sync_label()).style_spec(branch.sync.map_or("", SyncState::style_spec))
+        }
+        Column::Percent => Cell::new(&branch.percent_label(glyphs)).style_spec("r"),
+        Column::BehindAuthors => Cell::new(&branch.behind_authors_label()),
+        Column::AheadBehind => Cell::new(&branch.format_chart_line(
+            max,
+            label_width,
+            bar_width,
+            opt.chart_style,
+            opt.reverse_bars,
+            glyphs,
+        ))
+        .style_spec(if branch.compare_error.is_some() {
+            "Fr"
+        } else {
+            ""
+        }),
+        Column::UpstreamAheadBehind => match branch.upstream_branch() {
+            Some(upstream_branch) => Cell::new(&upstream_branch.format_chart_line(
+                upstream_max,
+                upstream_label_width,
+                bar_width,
+                opt.chart_style,
+                opt.reverse_bars,
+                glyphs,
+            )),
+            None => Cell::new(""),
+        },
+    };
+
+    let mut rows: Vec<Vec<Cell>> = branches
+        .iter()
+        .map(|branch| {
+            columns
+                .iter()
+                .map(|column| cell(branch, column, BRANCH_CHARACTERS_COUNT))
+                .collect()
+        })
+        .collect();
+
+    // Shrink charts if the table would be wider than the output, or wrap in the terminal
+    let chart_indexes: Vec<_> = (0..columns.len())
+        .filter(|index| columns[*index].is_chart())
+        .collect();
+    let output_width = opt
+        .output_width
+        .or_else(console::width)
+        .filter(|_| !chart_indexes.is_empty());
+    if let Some(output_width) = output_width {
+        // Each cell is padded on both sides, and columns are separated by one character
+        let cell_padding = 2 * opt.padding;
+        let other_columns_width: usize = (0..columns.len())
+            .filter(|index| !chart_indexes.contains(index))
+            .map(|index| {
+                rows.iter()
+                    .map(|row| row[index].get_content().width())
+                    .max()
+                    .unwrap_or(0)
+                    + cell_padding
+            })
+            .sum();
+        let separators_width = columns.len() - 1;
+        let borders_width = if opt.table_style == TableStyle::Bordered {
+            2
+        } else {
+            0
+        };
+        let charts_decorations_width: usize = chart_indexes
+            .iter()
+            .map(|index| 2 * chart_label_width(&columns[*index]) + 3 + cell_padding)
+            .sum();
+        let bar_width = (output_width.saturating_sub(
+            other_columns_width + charts_decorations_width + separators_width + borders_width,
+        ) / (2 * chart_indexes.len()))
+        .clamp(MIN_BRANCH_CHARACTERS_COUNT, BRANCH_CHARACTERS_COUNT);
+
+        if bar_width < BRANCH_CHARACTERS_COUNT {
+            for (row, branch) in rows.iter_mut().zip(branches) {
+                for index in &chart_indexes {
+                    row[*index] = cell(branch, &columns[*index], bar_width);
+                }
+            }
+        }
+    }
+
+    let mut group_start = 0;
+    for (index, (branch, row)) in branches.iter().zip(rows).enumerate() {
+        if let Some(group_by) = opt.group_by {
+            let group = branch.group(group_by);
+            if index == 0 || branches[index - 1].group(group_by) != group {
+                group_start = index;
+                let label = group.unwrap_or(match group_by {
+                    GroupBy::Remote => "local",
+                    GroupBy::Prefix => "(no prefix)",
+                });
+                table.add_row(Row::new(vec![Cell::new(label)
+                    .style_spec("b")
+                    .with_hspan(columns.len())]));
+            }
+        }
+
+        if opt
+            .highlight_patterns
+            .iter()
+            .any(|pattern| matches_pattern(pattern, &branch.name))
+        {
+            table.add_row(Row::new(
+                row.into_iter()
+                    .map(|cell| cell.with_style(Attr::Bold))
+                    .collect(),
+            ));
+        } else {
+            table.add_row(Row::new(row));
+        }
+
+        if let Some(group_by) = opt.group_by.filter(|_| opt.group_summary) {
+            let group = branch.group(group_by);
+            if branches
+                .get(index + 1)
+                .map_or(true, |next| next.group(group_by) != group)
+            {
+                // Use separate cells, as a spanning cell would widen the first column
+                let members = &branches[group_start..=index];
+                table.add_row(Row::new(
+                    columns
+                        .iter()
+                        .map(|column| match column {
+                            Column::Name => Cell::new(&format!(
+                                "{} branch{}",
+                                members.len(),
+                                if members.len() == 1 { "" } else { "es" }
+                            ))
+                            .style_spec("i"),
+                            Column::AheadBehind => Cell::new(&format!(
+                                "{} behind, {} ahead",
+                                members.iter().map(|branch| branch.behind).sum::<usize>(),
+                                members.iter().map(|branch| branch.ahead).sum::<usize>(),
+                            ))
+                            .style_spec("ci"),
+                            _ => Cell::new(""),
+                        })
+                        .collect(),
+                ));
+            }
+        }
+    }
+
+    table.printstd();
+    print_synced_count(opt, synced_count, false);
+
+    if opt.legend && columns.contains(&Column::AheadBehind) {
+        print_legend(opt, glyphs);
+    }
+}
+
+/// Largest count to scale chart bars to, and width of count labels
+fn chart_scale(opt: &Opt, branches: &[FormatedBranch]) -> (usize, usize) {
+    // Unrelated branches don't have a chart, don't let their counts scale others
+    let max = branches
+        .iter()
+        .filter(|branch| !branch.unrelated)
+        .map(|branch| branch.ahead.max(branch.behind))
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    let label_width = branches
+        .iter()
+        .filter(|branch| !branch.unrelated)
+        .map(|branch| branch.ahead_label().width().max(branch.behind_label().width()))
+        .max()
+        .unwrap_or(0)
+        .max(number_size(max))
+        // Room for the sign of reversed labels
+        + usize::from(opt.reverse_bars);
+
+    (max, label_width)
+}
+
+/// What branches are compared with
+fn base_label(opt: &Opt) -> &'static str {
+    if opt.since_divergence {
+        "divergence point"
+    } else if opt.compare_with_upstream_branches {
+        "upstream"
+    } else if opt.compare_with_local_branches {
+        "local branch"
+    } else {
+        "base"
+    }
+}
+
+/// Print git commands equivalent to the computations done with the given options
+fn explain(opt: &Opt) {
+    if opt.branches_from_stdin {
+        eprintln!("# Branches are read from the standard input");
+    } else {
+        let refs = if opt.all_branches || (opt.remote_branches && opt.local_branches) {
+            "refs/heads refs/remotes"
+        } else if opt.remote_branches {
+            "refs/remotes"
+        } else {
+            "refs/heads"
+        };
+        eprintln!("# List branches");
+        eprintln!("git for-each-ref --format='%(refname:short)' {}", refs);
+    }
+
+    let base = if opt.compare_with_upstream_branches {
+        "<branch>@{upstream}".to_string()
+    } else if opt.compare_with_local_branches {
+        "<branch name without remote>".to_string()
+    } else if let Some(tag) = opt.since_tag.as_ref() {
+        format!("tags/{}", tag)
+    } else if let Some(count) = opt.relative_base {
+        format!("HEAD~{}", count)
+    } else if let [a, b] = opt.merge_base_revisions.as_slice() {
+        format!("$(git merge-base {} {})", a, b)
+    } else {
+        opt.base_revision.clone()
+    };
+    let base = if opt.since_divergence {
+        format!("$(git merge-base {} <branch>)", base)
+    } else {
+        base
+    };
+
+    let merges = match opt.merges() {
+        Merges::Included => "",
+        Merges::Only => " --merges",
+        Merges::Excluded => " --no-merges",
+    };
+    eprintln!("# For each branch, count commits behind and ahead of its base");
+    match opt.count_cap {
+        Some(cap) => {
+            eprintln!(
+                "git rev-list --count{} --max-count={} <branch>..{}",
+                merges, cap, base
+            );
+            eprintln!(
+                "git rev-list --count{} --max-count={} {}..<branch>",
+                merges, cap, base
+            );
+        }
+        None => eprintln!(
+            "git rev-list --left-right --count{} {}...<branch>",
+            merges, base
+        ),
+    }
+    if !opt.compare_with_upstream_branches && !opt.compare_with_local_branches {
+        for rule in &opt.base_rules {
+            eprintln!(
+                "# with '{}' instead of '{}' as base for branches matching '{}'",
+                rule.revision, base, rule.pattern
+            );
+        }
+    }
+
+    if opt.show_upstream_chart {
+        eprintln!("# For each local branch, also count commits behind and ahead of its upstream");
+        eprintln!("git rev-list --left-right --count <branch>@{{upstream}}...<branch>");
+    }
+
+    if let Some(merged_into) = opt.merged_into.as_ref() {
+        eprintln!("# Only keep branches merged into '{}'", merged_into);
+        eprintln!("git merge-base --is-ancestor <branch> {}", merged_into);
+    }
+    eprintln!();
+}
+
+fn print_legend(opt: &Opt, glyphs: &Glyphs) {
+    let base = base_label(opt);
+    println!();
+    if opt.reverse_bars {
+        println!("Left number: commits ahead of the {}", base);
+        println!("Right number: commits behind the {}", base);
+    } else {
+        println!("Left number: commits behind the {}", base);
+        println!("Right number: commits ahead of the {}", base);
+    }
+    match opt.chart_style {
+        ChartStyle::Split => {
+            let (middle_ahead, middle_behind) = if opt.reverse_bars {
+                (glyphs.middle_behind, glyphs.middle_ahead)
+            } else {
+                (glyphs.middle_ahead, glyphs.middle_behind)
+            };
+            println!(
+                "{} in sync, {} ahead only, {} behind only, {} diverged",
+                glyphs.middle, middle_ahead, middle_behind, glyphs.middle_diverged
+            );
+            println!(
+                "{} bars are scaled to the largest count, {} and {} end half characters",
+                glyphs.bar, glyphs.behind_half_bar, glyphs.ahead_half_bar
+            );
+        }
+        ChartStyle::Stacked => {
+            println!(
+                "{} commits behind, {} commits ahead, scaled to the largest count",
+                glyphs.stacked_behind_bar, glyphs.stacked_ahead_bar
+            );
+        }
+        ChartStyle::Ratio => {
+            println!(
+                "{} commits behind, {} commits ahead, as shares of the bar, {} in sync",
+                glyphs.stacked_behind_bar, glyphs.stacked_ahead_bar, glyphs.middle
+            );
+        }
+    }
+    if opt.show_upstream_chart {
+        println!("The second chart compares local branches with their upstream, scaled separately");
+    }
+}
+
+fn main() {
+    run().unwrap_or_else(|error: CliError| {
+        let message = match error {
             CliError::GitError(error) => error.message().to_string(),
+            CliError::IoError(error) => error.to_string(),
+            CliError::UnknownRevision(revision, suggestions) => {
+                let mut message = format!("revision '{}' not found", revision);
+                if let [suggestion] = suggestions.as_slice() {
+                    write!(message, "\n\nDid you mean '{}'?", suggestion).unwrap();
+                } else if !suggestions.is_empty() {
+                    message.push_str("\n\nDid you mean one of these?");
+                    for suggestion in suggestions {
+                        write!(message, "\n    {}", suggestion).unwrap();
+                    }
+                }
+                message
+            }
+            CliError::UnknownTag(tag) => format!("tag '{}' not found", tag),
+            CliError::HistoryTooShort(count, length) => format!(
+                "cannot go {} commits back from HEAD, its history only has {} first-parent \
+                 ancestors",
+                count, length
+            ),
+            CliError::ReflogTooShort(name, index, length) => format!(
+                "cannot go {} entries back in the reflog of '{}', it only has {} entr{}",
+                index,
+                name,
+                length,
+                if length == 1 { "y" } else { "ies" }
+            ),
+            CliError::AmbiguousRevision(name) => format!(
+                "'{0}' is both a branch and a tag, use 'heads/{0}' or 'tags/{0}'",
+                name
+            ),
+            CliError::NoMergeBase(a, b) => {
+                format!("'{}' and '{}' have no common history", a, b)
+            }
+            CliError::InvalidTemplate(path, error) => {
+                format!("invalid template '{}': {}", path.display(), error)
+            }
+            CliError::NoBranches => "no branches to show".to_string(),
+            CliError::StaleBranches(days, branches) => format!(
+                "{} branch{} with no commit for more than {} day{}: {}",
+                branches.len(),
+                if branches.len() == 1 { "" } else { "es" },
+                days,
+                if days == 1 { "" } else { "s" },
+                branches.join(", ")
+            ),
         };
-        println!("Error: {}", message);
+        eprintln!("Error: {}", message);
+        std::process::exit(1);
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Signature;
+    use std::{cmp::Ordering, fs, ops::Deref, process::Command};
+
+    /// Repository in a temporary directory, removed at the end of the test
+    struct TestRepository {
+        path: PathBuf,
+        repo: Repository,
+    }
+
+    impl Deref for TestRepository {
+        type Target = Repository;
+
+        fn deref(&self) -> &Repository {
+            &self.repo
+        }
+    }
+
+    impl Drop for TestRepository {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+
+    fn test_repository(name: &str) -> TestRepository {
+        let path = std::env::temp_dir().join(format!(
+            "git-branches-overview-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&path);
+        let repo = Repository::init(&path).unwrap();
+        TestRepository { path, repo }
+    }
+
+    fn commit(repo: &Repository, message: &str) -> Oid {
+        let signature = Signature::now("test", "test@example.com").unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let parents: Vec<_> = parent.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .unwrap()
+    }
+
+    fn branch(last_commit_time: i64, remote: Option<&str>, name: &str) -> FormatedBranch {
+        FormatedBranch {
+            kind: if remote.is_some() {
+                RefKind::Remote
+            } else {
+                RefKind::Local
+            },
+            last_commit_time,
+            last_commit_offset: 0,
+            tip_summary: String::new(),
+            tip_author: String::new(),
+            note: None,
+            shorthand: match remote {
+                Some(remote) => format!("{}/{}", remote, name),
+                None => name.into(),
+            },
+            name: name.into(),
+            remote: remote.map(Into::into),
+            remotes: remote.iter().map(|remote| remote.to_string()).collect(),
+            is_head: false,
+            lossy_name: false,
+            tip: Oid::zero(),
+            signed: false,
+            sync: None,
+            upstream_divergence: None,
+            behind: 0,
+            behind_capped: false,
+            ahead: 0,
+            ahead_capped: false,
+            behind_authors: Vec::new(),
+            behind_authors_capped: false,
+            unrelated: false,
+            at_base: false,
+            merged: false,
+            compare_error: None,
+        }
+    }
+
+    #[test]
+    fn read_git_branch_output() {
+        let output =
+            "* main\n+ worktree\n  remotes/origin/HEAD -> origin/main\n  remotes/origin/x\n\n";
+        assert_eq!(
+            read_branch_names(output.as_bytes()).unwrap(),
+            ["main", "worktree", "origin/x"]
+        );
+    }
+
+    #[test]
+    fn fit_width_counts_display_columns() {
+        // Wide characters take two columns, combining characters none
+        assert_eq!(fit_width("日本", 6, "…"), "日本  ");
+        assert_eq!(fit_width("日本語", 4, "…"), "日… ");
+        assert_eq!(fit_width("日本語", 5, "…"), "日本…");
+        assert_eq!(fit_width("e\u{301}te\u{301}", 4, "…"), "e\u{301}te\u{301} ");
+        assert_eq!(fit_width("e\u{301}te\u{301}s", 2, "…"), "e\u{301}…");
+    }
+
+    #[test]
+    fn chart_lines_have_the_same_width() {
+        let branches = [
+            FormatedBranch {
+                behind: 3,
+                ahead: 12,
+                ..branch(10, None, "feature/日本語")
+            },
+            FormatedBranch {
+                ahead: 1,
+                ..branch(10, None, "e\u{301}te\u{301}")
+            },
+            FormatedBranch {
+                unrelated: true,
+                ..branch(10, None, "orphan")
+            },
+        ];
+        for style in [ChartStyle::Split, ChartStyle::Stacked, ChartStyle::Ratio] {
+            for reverse in [false, true] {
+                for branch in &branches {
+                    let line = branch.format_chart_line(12, 3, 8, style, reverse, &UNICODE_GLYPHS);
+                    assert_eq!(line.width(), 2 * 8 + 2 * 3 + 3, "{:?}", line);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn render_template_placeholders() {
+        let opt = Opt::from_iter(&["git-branches-overview"]);
+        let template: output::Template = "{remote}/{name}: -{behind} +{ahead} {{}}\n"
+            .parse()
+            .unwrap();
+        let branches = [FormatedBranch {
+            behind: 2,
+            ahead: 3,
+            ..branch(10, Some("origin"), "develop")
+        }];
+
+        assert_eq!(
+            template.render(&opt, &branches),
+            "origin/develop: -2 +3 {}\n"
+        );
+        assert!("{unknown}".parse::<output::Template>().is_err());
+        assert!("{ahead-behind}".parse::<output::Template>().is_err());
+        assert!("{name".parse::<output::Template>().is_err());
+        assert!("name}".parse::<output::Template>().is_err());
+    }
+
+    #[test]
+    fn sort_keeps_enumeration_order_of_identical_keys() {
+        let mut branches = [
+            FormatedBranch {
+                ahead: 1,
+                ..branch(10, Some("origin"), "master")
+            },
+            branch(20, None, "feature"),
+            FormatedBranch {
+                ahead: 2,
+                ..branch(10, Some("origin"), "master")
+            },
+            FormatedBranch {
+                ahead: 3,
+                ..branch(10, Some("origin"), "master")
+            },
+        ];
+
+        branches.sort_by(compare_branches);
+
+        let order: Vec<_> = branches.iter().map(|branch| branch.ahead).collect();
+        assert_eq!(order, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn sort_orders_by_time_then_remote_then_name() {
+        let mut branches = [
+            branch(10, Some("origin"), "b"),
+            branch(10, Some("origin"), "a"),
+            branch(10, None, "z"),
+            branch(10, Some("backup"), "z"),
+            branch(30, Some("origin"), "old"),
+        ];
+
+        branches.sort_by(compare_branches);
+
+        let order: Vec<_> = branches
+            .iter()
+            .map(|branch| (branch.remote.as_deref(), branch.name.as_str()))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                (Some("origin"), "old"),
+                (None, "z"),
+                (Some("backup"), "z"),
+                (Some("origin"), "a"),
+                (Some("origin"), "b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn compare_is_total_on_distinct_branches() {
+        let branches = [
+            branch(10, None, "master"),
+            branch(10, None, "feature"),
+            branch(10, Some("origin"), "master"),
+            branch(10, Some("backup"), "master"),
+            branch(20, None, "master"),
+            branch(20, Some("origin"), "feature"),
+        ];
+
+        for (i, a) in branches.iter().enumerate() {
+            for (j, b) in branches.iter().enumerate() {
+                let ordering = compare_branches(a, b);
+                assert_eq!(ordering == Ordering::Equal, i == j, "{} vs {}", i, j);
+                assert_eq!(ordering, compare_branches(b, a).reverse());
+            }
+        }
+    }
+
+    #[test]
+    fn resolve_remote_branch_as_base() {
+        let repo = test_repository("remote-base");
+        let first = commit(&repo, "first");
+        commit(&repo, "second");
+        repo.reference("refs/remotes/origin/develop", first, false, "test")
+            .unwrap();
+
+        assert_eq!(resolve_revision(&repo, "origin/develop").unwrap(), first);
+    }
+
+    #[test]
+    fn resolve_symbolic_reference_as_base() {
+        let repo = test_repository("symbolic-base");
+        commit(&repo, "first");
+        let second = commit(&repo, "second");
+        repo.reference("refs/remotes/origin/main", second, false, "test")
+            .unwrap();
+        repo.reference_symbolic(
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/main",
+            false,
+            "test",
+        )
+        .unwrap();
+
+        assert_eq!(resolve_revision(&repo, "origin/HEAD").unwrap(), second);
+        assert_eq!(resolve_revision(&repo, "origin").unwrap(), second);
+    }
+
+    #[test]
+    fn reject_ambiguous_revisions() {
+        let repo = test_repository("ambiguous-base");
+        let first = commit(&repo, "first");
+        let second = commit(&repo, "second");
+        repo.reference("refs/heads/release", second, false, "test")
+            .unwrap();
+        repo.reference("refs/tags/release", first, false, "test")
+            .unwrap();
+
+        match resolve_revision(&repo, "release") {
+            Err(CliError::AmbiguousRevision(name)) => assert_eq!(name, "release"),
+            _ => panic!("expected an ambiguous revision error"),
+        }
+        assert_eq!(resolve_revision(&repo, "heads/release").unwrap(), second);
+        assert_eq!(resolve_revision(&repo, "tags/release").unwrap(), first);
+    }
+
+    #[test]
+    fn resolve_reflog_entries() {
+        let repo = test_repository("reflog-base");
+        let first = commit(&repo, "first");
+        let second = commit(&repo, "second");
+        let branch = repo.head().unwrap().shorthand().unwrap().to_string();
+
+        assert_eq!(
+            resolve_revision(&repo, &format!("{}@{{0}}", branch)).unwrap(),
+            second
+        );
+        assert_eq!(
+            resolve_revision(&repo, &format!("{}@{{1}}", branch)).unwrap(),
+            first
+        );
+        assert_eq!(resolve_revision(&repo, "@{1}").unwrap(), first);
+        match resolve_revision(&repo, &format!("{}@{{5}}", branch)) {
+            Err(CliError::ReflogTooShort(name, 5, 2)) => assert_eq!(name, branch),
+            _ => panic!("expected a too short reflog error"),
+        }
+    }
+
+    #[test]
+    fn resolve_annotated_tag_to_its_commit() {
+        let repo = test_repository("tag-base");
+        let first = commit(&repo, "first");
+        let signature = Signature::now("test", "test@example.com").unwrap();
+        repo.tag(
+            "v1.0",
+            &repo.find_object(first, None).unwrap(),
+            &signature,
+            "v1.0",
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(resolve_revision(&repo, "v1.0").unwrap(), first);
+    }
+
+    #[test]
+    fn suggest_branches_for_unknown_revision() {
+        let repo = test_repository("unknown-base");
+        let first = commit(&repo, "first");
+        repo.reference("refs/remotes/origin/develop", first, false, "test")
+            .unwrap();
+        repo.reference("refs/remotes/backup/develop", first, false, "test")
+            .unwrap();
+
+        match resolve_revision(&repo, "upstream/develop") {
+            Err(CliError::UnknownRevision(revision, suggestions)) => {
+                assert_eq!(revision, "upstream/develop");
+                assert_eq!(suggestions, vec!["backup/develop", "origin/develop"]);
+            }
+            _ => panic!("expected an unknown revision error"),
+        }
+    }
+
+    #[test]
+    fn suggest_close_revisions_for_typos() {
+        let repo = test_repository("typo-base");
+        let first = commit(&repo, "first");
+        repo.branch("develop", &repo.find_commit(first).unwrap(), false)
+            .unwrap();
+        repo.tag_lightweight("v1.0", &repo.find_object(first, None).unwrap(), false)
+            .unwrap();
+
+        match resolve_revision(&repo, "devlop") {
+            Err(CliError::UnknownRevision(_, suggestions)) => {
+                assert_eq!(suggestions, vec!["develop"]);
+            }
+            _ => panic!("expected an unknown revision error"),
+        }
+
+        match resolve_revision(&repo, "v1.1") {
+            Err(CliError::UnknownRevision(_, suggestions)) => {
+                assert_eq!(suggestions, vec!["v1.0"]);
+            }
+            _ => panic!("expected an unknown revision error"),
+        }
+    }
+
+    fn git(repo: &Repository, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .current_dir(repo.workdir().unwrap())
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+        String::from_utf8(output.stdout).unwrap().trim().into()
+    }
+
+    #[test]
+    fn counts_match_git_rev_list() {
+        let repo = test_repository("rev-list");
+        git(&repo, &["symbolic-ref", "HEAD", "refs/heads/main"]);
+        for message in ["c1", "c2", "c3"] {
+            git(&repo, &["commit", "-q", "--allow-empty", "-m", message]);
+        }
+
+        // Ahead, behind and diverged branches
+        git(&repo, &["checkout", "-q", "-b", "ahead"]);
+        git(&repo, &["commit", "-q", "--allow-empty", "-m", "a1"]);
+        git(&repo, &["commit", "-q", "--allow-empty", "-m", "a2"]);
+        git(&repo, &["branch", "behind", "main~2"]);
+        git(&repo, &["checkout", "-q", "-b", "diverged", "main~1"]);
+        git(&repo, &["commit", "-q", "--allow-empty", "-m", "d1"]);
+
+        // Merge of the base into a branch
+        git(&repo, &["checkout", "-q", "-b", "merge", "main~2"]);
+        git(&repo, &["commit", "-q", "--allow-empty", "-m", "m1"]);
+        git(&repo, &["merge", "-q", "--no-ff", "-m", "merge", "main~1"]);
+
+        // Octopus merge of several side branches
+        for side in ["side1", "side2", "side3"] {
+            git(&repo, &["checkout", "-q", "-b", side, "main~2"]);
+            git(&repo, &["commit", "-q", "--allow-empty", "-m", side]);
+        }
+        git(&repo, &["checkout", "-q", "-b", "octopus", "main~1"]);
+        git(
+            &repo,
+            &[
+                "merge", "-q", "--no-ff", "-m", "octopus", "side1", "side2", "side3",
+            ],
+        );
+        git(&repo, &["checkout", "-q", "main"]);
+
+        let path = repo.workdir().unwrap().to_str().unwrap().to_string();
+        for (extra_args, rev_list_args) in [
+            (vec![], vec![]),
+            (vec!["--compare-commit-count-cap", "1000"], vec![]),
+            (vec!["--count-merges-only"], vec!["--merges"]),
+            (vec!["--exclude-merges"], vec!["--no-merges"]),
+        ] {
+            let mut args = vec!["git-branches-overview", "--repo-dir", &path, "main"];
+            args.extend(extra_args);
+            let opt = Opt::from_iter(args);
+            let bases = Bases::resolve(&repo, &opt).unwrap();
+
+            for name in ["ahead", "behind", "diverged", "merge", "octopus", "side1"] {
+                let branch = repo.find_branch(name, BranchType::Local).unwrap();
+                let branch = match FormatedBranch::from_branch(&repo, &branch, &opt, &bases) {
+                    Ok(branch) => branch,
+                    Err(_) => panic!("branch {} was skipped", name),
+                };
+                let range = format!("main...{}", name);
+                let mut rev_list = vec!["rev-list", "--left-right", "--count", &range];
+                rev_list.extend(&rev_list_args);
+                assert_eq!(
+                    format!("{}\t{}", branch.behind, branch.ahead),
+                    git(&repo, &rev_list),
+                    "counts of {} with {:?}",
+                    name,
+                    rev_list_args
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn format_time_in_author_time_zone() {
+        let format = "%Y-%m-%d %H:%M:%S %z %:z %%";
+        assert_eq!(
+            format_time(951_879_600, -210, format),
+            "2000-02-29 23:30:00 -0330 -03:30 %"
+        );
+        assert_eq!(
+            format_time(-7200, 60, format),
+            "1969-12-31 23:00:00 +0100 +01:00 %"
+        );
+        assert!(parse_time_format("%Y-%m-%d").is_ok());
+        assert!(parse_time_format("%Y-%q").is_err());
+        assert!(parse_time_format("%:y").is_err());
+    }
+
+    #[test]
+    fn abbrev_length_honors_conditional_includes() {
+        let repo = test_repository("include-if");
+        let included = repo.path().join("included.config");
+        fs::write(&included, "[core]\n\tabbrev = 10\n").unwrap();
+        repo.config()
+            .unwrap()
+            .set_str(
+                &format!("includeIf.gitdir:{}.path", repo.path().display()),
+                included.to_str().unwrap(),
+            )
+            .unwrap();
+
+        let repo = Repository::open(repo.path()).unwrap();
+        assert_eq!(abbrev_length(&repo), 10);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn open_repository_with_backslashes() {
+        let repo = test_repository("backslashes");
+        let path = repo.workdir().unwrap().to_str().unwrap().replace('/', "\\");
+        let opt = Opt::from_iter(&["git-branches-overview", "--repo-dir", &path]);
+
+        assert_eq!(open_repository(&opt).unwrap().path(), repo.path());
+    }
+
+    #[test]
+    fn delete_commands_keep_unmerged_and_base_branches() {
+        let repo = test_repository("delete-commands");
+        git(&repo, &["symbolic-ref", "HEAD", "refs/heads/main"]);
+        for message in ["c1", "c2", "c3"] {
+            git(&repo, &["commit", "-q", "--allow-empty", "-m", message]);
+        }
+        git(&repo, &["branch", "old", "main~1"]);
+        git(&repo, &["checkout", "-q", "-b", "feature"]);
+        git(&repo, &["commit", "-q", "--allow-empty", "-m", "f1"]);
+        git(&repo, &["checkout", "-q", "main"]);
+
+        // A remote behind main, with its own copy of each branch
+        git(
+            &repo,
+            &["remote", "add", "origin", "https://example.com/repo.git"],
+        );
+        for (name, revision) in [("main", "main~1"), ("old", "old"), ("feature", "feature")] {
+            let remote_ref = format!("refs/remotes/origin/{}", name);
+            git(&repo, &["update-ref", &remote_ref, revision]);
+        }
+        git(
+            &repo,
+            &[
+                "symbolic-ref",
+                "refs/remotes/origin/HEAD",
+                "refs/remotes/origin/main",
+            ],
+        );
+        for name in ["main", "feature"] {
+            let upstream = format!("origin/{}", name);
+            git(
+                &repo,
+                &["branch", "-q", "--set-upstream-to", &upstream, name],
+            );
+        }
+
+        let path = repo.workdir().unwrap().to_str().unwrap().to_string();
+        let deletable = |extra_args: &[&str]| {
+            let mut args = vec!["git-branches-overview", "--repo-dir", &path];
+            args.extend(extra_args);
+            args.push("--print-delete-commands");
+            let opt = Opt::from_iter(args);
+            let bases = Bases::resolve(&repo, &opt).unwrap();
+            let mut names: Vec<_> = repo
+                .branches(None)
+                .unwrap()
+                .filter_map(|result| {
+                    let branch =
+                        FormatedBranch::from_branch(&repo, &result.unwrap().0, &opt, &bases)
+                            .ok()?;
+                    Some(branch.shorthand.clone()).filter(|_| branch.is_deletable(&opt))
+                })
+                .collect();
+            names.sort();
+            names
+        };
+
+        // In sync with its upstream, but ahead of main
+        assert_eq!(deletable(&["-u"]), Vec::<String>::new());
+        // origin/main is the upstream of the base branch
+        assert_eq!(deletable(&["--local-name"]), ["origin/old"]);
+        // origin/main is the default branch of origin
+        assert_eq!(deletable(&["-a"]), ["old", "origin/old"]);
+    }
+}
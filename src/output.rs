@@ -0,0 +1,644 @@
+//! Output formats other than the default table.
+//!
+//! Document formats (CSV, TSV, Markdown, HTML) use the selected columns, the 'ahead-behind' chart
+//! being replaced by two numeric 'behind' and 'ahead' columns, and the upstream chart by
+//! 'upstream-behind' and 'upstream-ahead' columns.  JSON always includes every field.
+
+use super::{
+    fit_width, format_age, format_time, names_width, now, Column, FormatedBranch, Opt,
+    UNICODE_GLYPHS,
+};
+use std::{fmt::Write, str::FromStr};
+
+fn headers(columns: &[Column]) -> Vec<&'static str> {
+    let mut headers = Vec::new();
+    for column in columns {
+        match column {
+            Column::AheadBehind => {
+                headers.push("behind");
+                headers.push("ahead");
+            }
+            Column::UpstreamAheadBehind => {
+                headers.push("upstream-behind");
+                headers.push("upstream-ahead");
+            }
+            column => headers.push(column.name()),
+        }
+    }
+    headers
+}
+
+/// Value of a column, the 'ahead-behind' chart being split in 'behind' and 'ahead' values
+fn value(opt: &Opt, branch: &FormatedBranch, column: &Column, now: i64) -> String {
+    match column {
+        Column::RefType => branch.kind.name().into(),
+        Column::Remote => branch.remote_label(),
+        Column::Name => branch.display_name(opt),
+        Column::Age => format_age(now - branch.last_commit_time),
+        Column::Date => format_time(
+            branch.last_commit_time,
+            branch.last_commit_offset,
+            &opt.time_format,
+        ),
+        Column::TrackingSign => branch.format_tracking_sign(&UNICODE_GLYPHS),
+        Column::Oid => branch.oid_label(opt),
+        Column::Subject => branch.tip_summary.clone(),
+        Column::Note => branch.note.clone().unwrap_or_default(),
+        Column::Signature => branch.signature_label().into(),
+        Column::Sync => branch.sync_label().into(),
+        Column::Percent => branch.percent_label(&UNICODE_GLYPHS),
+        Column::BehindAuthors => branch.behind_authors_label(),
+        Column::AheadBehind | Column::UpstreamAheadBehind => {
+            unreachable!("the chart has no single value")
+        }
+    }
+}
+
+/// Commits behind, empty if they could not be counted
+pub fn behind_value(branch: &FormatedBranch) -> String {
+    match branch.compare_error {
+        Some(_) => String::new(),
+        None => branch.behind_label(),
+    }
+}
+
+/// Commits ahead, empty if they could not be counted
+pub fn ahead_value(branch: &FormatedBranch) -> String {
+    match branch.compare_error {
+        Some(_) => String::new(),
+        None => branch.ahead_label(),
+    }
+}
+
+fn values(opt: &Opt, branch: &FormatedBranch, columns: &[Column], now: i64) -> Vec<String> {
+    let mut values = Vec::new();
+    for column in columns {
+        match column {
+            Column::AheadBehind => {
+                values.push(behind_value(branch));
+                values.push(ahead_value(branch));
+            }
+            Column::UpstreamAheadBehind => match branch.upstream_divergence {
+                Some((ahead, behind)) => {
+                    values.push(behind.to_string());
+                    values.push(ahead.to_string());
+                }
+                None => {
+                    values.push(String::new());
+                    values.push(String::new());
+                }
+            },
+            column => values.push(value(opt, branch, column, now)),
+        }
+    }
+    values
+}
+
+fn json_string(value: &str) -> String {
+    let mut result = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(result, "\\u{:04x}", c as u32).unwrap(),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn json_option<T>(value: Option<T>, to_json: impl Fn(T) -> String) -> String {
+    value.map_or("null".into(), to_json)
+}
+
+fn json_list(values: impl Iterator<Item = String>) -> String {
+    format!("[{}]", values.collect::<Vec<_>>().join(", "))
+}
+
+/// Effective options, once implied ones are applied, as a JSON object keyed by option field names
+pub fn config_json(opt: &Opt) -> String {
+    let fields = [
+        ("base_revision", json_string(&opt.base_revision)),
+        ("autodetect_base", opt.autodetect_base.to_string()),
+        ("stdin_base", opt.stdin_base.to_string()),
+        (
+            "since_tag",
+            json_option(opt.since_tag.as_deref(), json_string),
+        ),
+        (
+            "relative_base",
+            json_option(opt.relative_base, |value| value.to_string()),
+        ),
+        (
+            "merge_base_revisions",
+            json_list(
+                opt.merge_base_revisions
+                    .iter()
+                    .map(|value| json_string(value)),
+            ),
+        ),
+        (
+            "base_rules",
+            json_list(
+                opt.base_rules
+                    .iter()
+                    .map(|rule| json_string(&format!("{}={}", rule.pattern, rule.revision))),
+            ),
+        ),
+        ("local_branches", opt.local_branches.to_string()),
+        ("remote_branches", opt.remote_branches.to_string()),
+        ("all_branches", opt.all_branches.to_string()),
+        (
+            "compare_with_upstream_branches",
+            opt.compare_with_upstream_branches.to_string(),
+        ),
+        (
+            "upstream_remote",
+            json_option(opt.upstream_remote.as_deref(), json_string),
+        ),
+        ("needs_push", opt.needs_push.to_string()),
+        ("needs_pull", opt.needs_pull.to_string()),
+        (
+            "compare_with_local_branches",
+            opt.compare_with_local_branches.to_string(),
+        ),
+        ("since_divergence", opt.since_divergence.to_string()),
+        (
+            "remotes",
+            json_list(opt.remotes.iter().map(|value| json_string(value))),
+        ),
+        (
+            "ignored_remotes",
+            json_list(opt.ignored_remotes.iter().map(|value| json_string(value))),
+        ),
+        (
+            "patterns",
+            json_list(opt.patterns.iter().map(|value| json_string(value))),
+        ),
+        (
+            "highlight_patterns",
+            json_list(
+                opt.highlight_patterns
+                    .iter()
+                    .map(|value| json_string(value)),
+            ),
+        ),
+        (
+            "filter_expr",
+            json_option(opt.filter_expr.as_ref(), |expr| json_string(expr.source())),
+        ),
+        (
+            "sort_expr",
+            json_option(opt.sort_expr.as_ref(), |expr| json_string(expr.source())),
+        ),
+        ("no_sort", opt.no_sort.to_string()),
+        ("branches_from_stdin", opt.branches_from_stdin.to_string()),
+        (
+            "merged_into",
+            json_option(opt.merged_into.as_deref(), json_string),
+        ),
+        (
+            "include_current_branch",
+            opt.include_current_branch.to_string(),
+        ),
+        (
+            "exclude_current_branch",
+            opt.exclude_current_branch.to_string(),
+        ),
+        (
+            "strip_prefix",
+            json_option(opt.strip_prefix.as_deref(), json_string),
+        ),
+        ("keep_remote_prefix", opt.keep_remote_prefix.to_string()),
+        (
+            "max_name_width",
+            json_option(opt.max_name_width, |value| value.to_string()),
+        ),
+        (
+            "count_cap",
+            json_option(opt.count_cap, |value| value.to_string()),
+        ),
+        ("count_merges_only", opt.count_merges_only.to_string()),
+        ("exclude_merges", opt.exclude_merges.to_string()),
+        ("unrelated_mode", json_string(opt.unrelated_mode.name())),
+        ("check_worktree", opt.check_worktree.to_string()),
+        ("dedupe", opt.dedupe.to_string()),
+        ("show_ref_type", opt.show_ref_type.to_string()),
+        ("hide_remote_label", opt.hide_remote_label.to_string()),
+        ("show_age", opt.show_age.to_string()),
+        ("show_date", opt.show_date.to_string()),
+        ("time_format", json_string(&opt.time_format)),
+        ("show_tracking_sign", opt.show_tracking_sign.to_string()),
+        ("show_oid", opt.show_oid.to_string()),
+        (
+            "oid_length",
+            json_option(opt.oid_length, |value| value.to_string()),
+        ),
+        ("oid_full", opt.oid_full.to_string()),
+        ("format", json_string(opt.format.name())),
+        (
+            "template_file",
+            json_option(opt.template_file.as_ref(), |path| {
+                json_string(&path.to_string_lossy())
+            }),
+        ),
+        (
+            "name_width_from_longest",
+            opt.name_width_from_longest.to_string(),
+        ),
+        (
+            "group_by",
+            json_option(opt.group_by, |value| json_string(value.name())),
+        ),
+        (
+            "color_by",
+            json_option(opt.color_by, |value| json_string(value.name())),
+        ),
+        (
+            "remote_order",
+            json_list(opt.remote_order.iter().map(|value| json_string(value))),
+        ),
+        ("group_summary", opt.group_summary.to_string()),
+        (
+            "columns",
+            json_list(opt.columns.iter().map(|column| json_string(column.name()))),
+        ),
+        (
+            "hidden_columns",
+            json_list(
+                opt.hidden_columns
+                    .iter()
+                    .map(|column| json_string(column.name())),
+            ),
+        ),
+        ("show_signature", opt.show_signature.to_string()),
+        ("show_subject", opt.show_subject.to_string()),
+        ("show_note", opt.show_note.to_string()),
+        (
+            "notes_ref",
+            json_option(opt.notes_ref.as_deref(), json_string),
+        ),
+        ("show_sync", opt.show_sync.to_string()),
+        ("show_upstream_chart", opt.show_upstream_chart.to_string()),
+        ("show_percent", opt.show_percent.to_string()),
+        ("show_behind_authors", opt.show_behind_authors.to_string()),
+        ("chart_style", json_string(opt.chart_style.name())),
+        ("reverse_bars", opt.reverse_bars.to_string()),
+        ("table_style", json_string(opt.table_style.name())),
+        ("ascii", opt.ascii.to_string()),
+        (
+            "separator",
+            json_option(opt.separator, |c| json_string(&c.to_string())),
+        ),
+        ("collapse_synced", opt.collapse_synced.to_string()),
+        ("legend", opt.legend.to_string()),
+        ("compact", opt.compact.to_string()),
+        (
+            "output_width",
+            json_option(opt.output_width, |value| value.to_string()),
+        ),
+        ("padding", opt.padding.to_string()),
+        ("fail_on_empty", opt.fail_on_empty.to_string()),
+        ("empty_ok", opt.empty_ok.to_string()),
+        (
+            "fail_on_stale",
+            json_option(opt.fail_on_stale, |value| value.to_string()),
+        ),
+        ("detect_duplicates", opt.detect_duplicates.to_string()),
+        ("verbose", opt.verbose.to_string()),
+        ("explain", opt.explain.to_string()),
+        ("null_terminated", opt.null_terminated.to_string()),
+        ("numeric_only", opt.numeric_only.to_string()),
+        (
+            "print_delete_commands",
+            opt.print_delete_commands.to_string(),
+        ),
+        ("count", opt.count.to_string()),
+        ("summary_json", opt.summary_json.to_string()),
+        ("watch", json_option(opt.watch, |value| value.to_string())),
+        (
+            "repo_path",
+            json_option(opt.repo_path.as_ref(), |path| {
+                json_string(&path.to_string_lossy())
+            }),
+        ),
+        (
+            "generate_completion",
+            json_option(opt.generate_completion.as_ref(), |shell| {
+                json_string(&shell.to_string())
+            }),
+        ),
+        ("list_branch_names", opt.list_branch_names.to_string()),
+        ("config_dump", opt.config_dump.to_string()),
+    ];
+    let mut result = String::from("{");
+    for (index, (name, value)) in fields.iter().enumerate() {
+        if index > 0 {
+            result.push(',');
+        }
+        write!(result, "\n  {}: {}", json_string(name), value).unwrap();
+    }
+    result.push_str("\n}\n");
+    result
+}
+
+pub fn json(branches: &[FormatedBranch]) -> String {
+    let mut result = String::from("[");
+    for (index, branch) in branches.iter().enumerate() {
+        if index > 0 {
+            result.push(',');
+        }
+        write!(
+            result,
+            "\n  {{\"name\": {}, \"remote\": {}, \"remotes\": [{}], \"kind\": {}, \"oid\": {}, \
+             \"last_commit_time\": {}, \"subject\": {}, \"note\": {}, \"behind\": {}, \"ahead\": {}, \"sync\": {}, \
+             \"upstream_behind\": {}, \"upstream_ahead\": {}, \
+             \"unrelated\": {}, \"error\": {}}}",
+            json_string(&branch.name),
+            branch.remote.as_deref().map_or("null".into(), json_string),
+            branch
+                .remotes
+                .iter()
+                .map(|remote| json_string(remote))
+                .collect::<Vec<_>>()
+                .join(", "),
+            json_string(branch.kind.name()),
+            json_string(&branch.tip.to_string()),
+            branch.last_commit_time,
+            json_string(&branch.tip_summary),
+            branch.note.as_deref().map_or("null".into(), json_string),
+            branch.behind,
+            branch.ahead,
+            branch
+                .sync
+                .map_or("null".into(), |sync| json_string(sync.name())),
+            branch
+                .upstream_divergence
+                .map_or("null".into(), |(_, behind)| behind.to_string()),
+            branch
+                .upstream_divergence
+                .map_or("null".into(), |(ahead, _)| ahead.to_string()),
+            branch.unrelated,
+            branch
+                .compare_error
+                .as_deref()
+                .map_or("null".into(), json_string),
+        )
+        .unwrap();
+    }
+    result.push_str("\n]\n");
+    result
+}
+
+/// Aggregate statistics, branches with no commit ahead being considered merged.  'ahead',
+/// 'behind', 'diverged' and 'synced' counts are exclusive.  Branches that could not be compared
+/// or are unrelated to their base are only counted in 'errors'.
+pub fn summary_json(branches: &[FormatedBranch]) -> String {
+    let (compared, errors): (Vec<_>, Vec<_>) = branches
+        .iter()
+        .partition(|branch| branch.compare_error.is_none() && !branch.unrelated);
+    let count = |predicate: &dyn Fn(&FormatedBranch) -> bool| {
+        compared.iter().filter(|branch| predicate(branch)).count()
+    };
+    format!(
+        "{{\"total\": {}, \"merged\": {}, \"unmerged\": {}, \"ahead\": {}, \"behind\": {}, \
+         \"diverged\": {}, \"synced\": {}, \"errors\": {}, \"max_ahead\": {}, \"max_behind\": {}, \
+         \"max_divergence\": {}}}\n",
+        compared.len() + errors.len(),
+        count(&|branch| branch.ahead == 0 && !branch.ahead_capped),
+        count(&|branch| branch.ahead > 0 || branch.ahead_capped),
+        count(&|branch| branch.ahead > 0 && branch.behind == 0),
+        count(&|branch| branch.ahead == 0 && branch.behind > 0),
+        count(&|branch| branch.ahead > 0 && branch.behind > 0),
+        count(&|branch| branch.ahead == 0 && branch.behind == 0),
+        errors.len(),
+        compared
+            .iter()
+            .map(|branch| branch.ahead)
+            .max()
+            .unwrap_or(0),
+        compared
+            .iter()
+            .map(|branch| branch.behind)
+            .max()
+            .unwrap_or(0),
+        compared
+            .iter()
+            .map(|branch| branch.ahead + branch.behind)
+            .max()
+            .unwrap_or(0),
+    )
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.into()
+    }
+}
+
+pub fn csv(opt: &Opt, branches: &[FormatedBranch]) -> String {
+    let columns = opt.columns();
+    let now = now();
+    let mut result = headers(&columns).join(",");
+    result.push('\n');
+    for branch in branches {
+        let values: Vec<_> = values(opt, branch, &columns, now)
+            .iter()
+            .map(|value| csv_field(value))
+            .collect();
+        result.push_str(&values.join(","));
+        result.push('\n');
+    }
+    result
+}
+
+/// Tabs can't appear in branch names, but subjects and notes are free text
+fn tsv_field(value: &str) -> String {
+    value.replace(['\t', '\n', '\r'], " ")
+}
+
+pub fn tsv(opt: &Opt, branches: &[FormatedBranch]) -> String {
+    let columns = opt.columns();
+    let now = now();
+    let mut result = headers(&columns).join("\t");
+    result.push('\n');
+    for branch in branches {
+        let values: Vec<_> = values(opt, branch, &columns, now)
+            .iter()
+            .map(|value| tsv_field(value))
+            .collect();
+        result.push_str(&values.join("\t"));
+        result.push('\n');
+    }
+    result
+}
+
+fn markdown_cell(value: &str) -> String {
+    value.replace('|', "\\|")
+}
+
+pub fn markdown(opt: &Opt, branches: &[FormatedBranch]) -> String {
+    let columns = opt.columns();
+    let now = now();
+    let headers = headers(&columns);
+    let mut result = String::new();
+    writeln!(result, "| {} |", headers.join(" | ")).unwrap();
+    writeln!(
+        result,
+        "|{}",
+        headers
+            .iter()
+            .map(|header| match *header {
+                "behind" | "ahead" | "upstream-behind" | "upstream-ahead" | "percent" => {
+                    " ---: |"
+                }
+                _ => " --- |",
+            })
+            .collect::<String>()
+    )
+    .unwrap();
+    for branch in branches {
+        let values: Vec<_> = values(opt, branch, &columns, now)
+            .iter()
+            .map(|value| markdown_cell(value))
+            .collect();
+        writeln!(result, "| {} |", values.join(" | ")).unwrap();
+    }
+    result
+}
+
+fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn html(opt: &Opt, branches: &[FormatedBranch]) -> String {
+    let columns = opt.columns();
+    let now = now();
+    let mut result = String::from("<table>\n  <thead>\n    <tr>");
+    for header in headers(&columns) {
+        write!(result, "<th>{}</th>", header).unwrap();
+    }
+    result.push_str("</tr>\n  </thead>\n  <tbody>\n");
+    for branch in branches {
+        result.push_str("    <tr>");
+        for value in values(opt, branch, &columns, now) {
+            write!(result, "<td>{}</td>", html_escape(&value)).unwrap();
+        }
+        result.push_str("</tr>\n");
+    }
+    result.push_str("  </tbody>\n</table>\n");
+    result
+}
+
+enum Segment {
+    Text(String),
+    Column(Column),
+    Behind,
+    Ahead,
+}
+
+/// Per-branch template, with placeholders named after columns
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = source.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err("unclosed placeholder".into()),
+                        }
+                    }
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(match name.as_str() {
+                        "behind" => Segment::Behind,
+                        "ahead" => Segment::Ahead,
+                        name => match name.parse() {
+                            Ok(Column::AheadBehind | Column::UpstreamAheadBehind) | Err(_) => {
+                                return Err(format!("unknown placeholder '{{{}}}'", name))
+                            }
+                            Ok(column) => Segment::Column(column),
+                        },
+                    });
+                }
+                '}' => return Err("unexpected '}', use '}}' for a literal brace".into()),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Ok(Template { segments })
+    }
+}
+
+impl Template {
+    /// Columns used by placeholders
+    pub fn columns(&self) -> Vec<Column> {
+        let mut columns = vec![Column::AheadBehind];
+        for segment in &self.segments {
+            if let Segment::Column(column) = segment {
+                columns.push(*column);
+            }
+        }
+        columns
+    }
+
+    pub fn render(&self, opt: &Opt, branches: &[FormatedBranch]) -> String {
+        let now = now();
+        let name_width = if opt.name_width_from_longest {
+            Some(names_width(opt, branches))
+        } else {
+            None
+        };
+        let mut result = String::new();
+        for branch in branches {
+            for segment in &self.segments {
+                match (segment, name_width) {
+                    (Segment::Text(text), _) => result.push_str(text),
+                    // Names are never truncated, as the width is the one of the longest
+                    (Segment::Column(Column::Name), Some(width)) => {
+                        result.push_str(&fit_width(&branch.display_name(opt), width, ""))
+                    }
+                    (Segment::Column(column), _) => {
+                        result.push_str(&value(opt, branch, column, now))
+                    }
+                    (Segment::Behind, _) => result.push_str(&behind_value(branch)),
+                    (Segment::Ahead, _) => result.push_str(&ahead_value(branch)),
+                }
+            }
+        }
+        result
+    }
+}